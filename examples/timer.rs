@@ -0,0 +1,17 @@
+use ic_logger::IcLogger;
+use log::LevelFilter;
+
+/// A callback as it would be registered with e.g. `ic_cdk_timers::set_timer_interval`.
+fn sweep(expired: usize) {
+    log::info!("removed {expired} expired sessions");
+}
+
+fn main() {
+    IcLogger::new().with_level(LevelFilter::Info).init().unwrap();
+
+    for round in 0..3 {
+        ic_logger::with_timer_context("sweep", || sweep(round));
+    }
+
+    log::info!("This line is not tagged with a timer.");
+}
@@ -0,0 +1,311 @@
+//! An in-memory ring buffer of recent log lines.
+//!
+//! Canister output is only visible in the replica logs, so keeping the most recent lines around
+//! lets a canister expose them through a query method.
+
+use std::collections::VecDeque;
+
+use candid::types::{Serializer, Type};
+use candid::CandidType;
+use log::Level;
+use serde::{Deserialize, Deserializer};
+
+/// A log line kept in the buffer.
+///
+/// Entries are [`CandidType`], with the level encoded as a variant, so that they can be returned
+/// from canister methods or shipped to other canisters as they are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The level of the record
+    pub level: Level,
+
+    /// The target of the record
+    pub target: String,
+
+    /// The formatted line, as it was printed
+    pub line: String,
+}
+
+/// The Candid representation of a [`LogEntry`].
+#[derive(CandidType, Deserialize)]
+struct CandidLogEntry {
+    level: CandidLevel,
+    target: String,
+    line: String,
+}
+
+/// The Candid representation of a [`Level`].
+#[derive(CandidType, Deserialize)]
+enum CandidLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<&LogEntry> for CandidLogEntry {
+    fn from(entry: &LogEntry) -> Self {
+        let level = match entry.level {
+            Level::Error => CandidLevel::Error,
+            Level::Warn => CandidLevel::Warn,
+            Level::Info => CandidLevel::Info,
+            Level::Debug => CandidLevel::Debug,
+            Level::Trace => CandidLevel::Trace,
+        };
+        CandidLogEntry {
+            level,
+            target: entry.target.clone(),
+            line: entry.line.clone(),
+        }
+    }
+}
+
+impl From<CandidLogEntry> for LogEntry {
+    fn from(entry: CandidLogEntry) -> Self {
+        let level = match entry.level {
+            CandidLevel::Error => Level::Error,
+            CandidLevel::Warn => Level::Warn,
+            CandidLevel::Info => Level::Info,
+            CandidLevel::Debug => Level::Debug,
+            CandidLevel::Trace => Level::Trace,
+        };
+        LogEntry {
+            level,
+            target: entry.target,
+            line: entry.line,
+        }
+    }
+}
+
+impl CandidType for LogEntry {
+    fn _ty() -> Type {
+        CandidLogEntry::ty()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        CandidLogEntry::from(self).idl_serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        CandidLogEntry::deserialize(deserializer).map(LogEntry::from)
+    }
+}
+
+/// What a buffer capped in bytes does with a line longer than the whole cap.
+///
+/// Such a line can't be kept next to any other entry, and evicting all of them would still not
+/// make room for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OversizedLinePolicy {
+    /// Drop the line and count it, see [`oversized_count`](crate::oversized_count)
+    #[default]
+    DropAndCount,
+    /// Cut the line down to the cap, so it replaces all other entries
+    TruncateToFit,
+}
+
+/// Keeps the last `capacity` entries, evicting the oldest ones.
+///
+/// If `max_bytes` is set, the oldest entries are also evicted until the lines of all entries add
+/// up to at most that many bytes.
+#[derive(Debug)]
+pub(crate) struct Buffer {
+    capacity: usize,
+    max_bytes: Option<usize>,
+    bytes: usize,
+    oversized: u64,
+    entries: VecDeque<LogEntry>,
+}
+
+impl Buffer {
+    pub(crate) fn new(capacity: usize, max_bytes: Option<usize>) -> Buffer {
+        Buffer {
+            capacity,
+            max_bytes,
+            bytes: 0,
+            oversized: 0,
+            /* A buffer capped in bytes has no meaningful number of entries to make room for */
+            entries: match max_bytes {
+                Some(_) => VecDeque::new(),
+                None => VecDeque::with_capacity(capacity),
+            },
+        }
+    }
+
+    /// The number of entries kept at most.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of bytes the lines of all entries add up to at most, if capped.
+    pub(crate) fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    /// The number of lines dropped for being longer than `max_bytes`.
+    pub(crate) fn oversized(&self) -> u64 {
+        self.oversized
+    }
+
+    pub(crate) fn push(&mut self, mut entry: LogEntry, policy: OversizedLinePolicy) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            if entry.line.len() > max_bytes {
+                match policy {
+                    OversizedLinePolicy::DropAndCount => {
+                        self.oversized += 1;
+                        return;
+                    }
+                    OversizedLinePolicy::TruncateToFit => {
+                        let end = (0..=max_bytes)
+                            .rev()
+                            .find(|end| entry.line.is_char_boundary(*end))
+                            .unwrap_or_default();
+                        entry.line.truncate(end);
+                    }
+                }
+            }
+            while self.bytes + entry.line.len() > max_bytes {
+                self.pop();
+            }
+        }
+        if self.entries.len() == self.capacity {
+            self.pop();
+        }
+        self.bytes += entry.line.len();
+        self.entries.push_back(entry);
+    }
+
+    /// Evict the oldest entry.
+    fn pop(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.bytes -= entry.line.len();
+        }
+    }
+
+    /// All entries, from the oldest to the most recent one.
+    pub(crate) fn entries(&self) -> impl DoubleEndedIterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    /// Remove and return all entries, from the oldest to the most recent one.
+    pub(crate) fn drain(&mut self) -> Vec<LogEntry> {
+        self.bytes = 0;
+        self.entries.drain(..).collect()
+    }
+
+    /// Join the lines of all entries into newline separated chunks of at most `max_bytes`.
+    ///
+    /// Lines are never split, so a line longer than `max_bytes` gets a chunk of its own. The
+    /// entries are taken from the oldest to the most recent one, or the other way around if
+    /// `newest_first` is set. The `header`, if any, is the first line of the first chunk.
+    pub(crate) fn chunks(&self, max_bytes: usize, newest_first: bool, header: Option<&str>) -> Vec<String> {
+        let entries: Box<dyn Iterator<Item = &LogEntry>> = match newest_first {
+            true => Box::new(self.entries().rev()),
+            false => Box::new(self.entries()),
+        };
+        let mut chunks = Vec::new();
+        let mut chunk = String::new();
+        for line in header.into_iter().chain(entries.map(|entry| entry.line.as_str())) {
+            if !chunk.is_empty() && chunk.len() + 1 + line.len() > max_bytes {
+                chunks.push(std::mem::take(&mut chunk));
+            }
+            if !chunk.is_empty() {
+                chunk.push('\n');
+            }
+            chunk.push_str(line);
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let mut buffer = Buffer::new(3, None);
+        for line in ["aaaa", "bbbb", "cccc", "dddd"] {
+            buffer.push(create_entry(line), OversizedLinePolicy::default());
+        }
+
+        let lines: Vec<_> = buffer.entries().map(|entry| entry.line.as_str()).collect();
+        assert_eq!(lines, ["bbbb", "cccc", "dddd"]);
+        assert_eq!(buffer.chunks(9, false, None), ["bbbb\ncccc", "dddd"]);
+        assert_eq!(buffer.chunks(8, false, None), ["bbbb", "cccc", "dddd"]);
+        assert_eq!(buffer.chunks(14, false, None), ["bbbb\ncccc\ndddd"]);
+        assert_eq!(buffer.chunks(2, false, None), ["bbbb", "cccc", "dddd"]);
+        assert_eq!(buffer.chunks(9, true, None), ["dddd\ncccc", "bbbb"]);
+        assert_eq!(buffer.chunks(9, true, Some("hh")), ["hh\ndddd", "cccc\nbbbb"]);
+        assert!(Buffer::new(3, None).chunks(10, false, None).is_empty());
+        assert_eq!(Buffer::new(3, None).chunks(10, false, Some("hh")), ["hh"]);
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let mut buffer = Buffer::new(usize::MAX, Some(12));
+        for line in ["aaaa", "bbbb", "cccc", "dd", "eeeeee"] {
+            buffer.push(create_entry(line), OversizedLinePolicy::default());
+        }
+
+        let lines: Vec<_> = buffer.entries().map(|entry| entry.line.as_str()).collect();
+        assert_eq!(lines, ["cccc", "dd", "eeeeee"]);
+        buffer.drain();
+        buffer.push(create_entry("ffffffffffff"), OversizedLinePolicy::default());
+        assert_eq!(buffer.entries().count(), 1);
+    }
+
+    #[test]
+    fn test_oversized_line() {
+        let lines = |buffer: &Buffer| buffer.entries().map(|entry| entry.line.clone()).collect::<Vec<_>>();
+
+        let mut buffer = Buffer::new(10, Some(10));
+        for line in ["aaaa", "bbbb", "oversized line"] {
+            buffer.push(create_entry(line), OversizedLinePolicy::DropAndCount);
+        }
+        assert_eq!(lines(&buffer), ["aaaa", "bbbb"]);
+        assert_eq!(buffer.oversized(), 1);
+
+        buffer.push(create_entry("cccc"), OversizedLinePolicy::DropAndCount);
+        assert_eq!(lines(&buffer), ["bbbb", "cccc"]);
+
+        let mut buffer = Buffer::new(10, Some(10));
+        for line in ["aaaa", "bbbb", "oversized liné"] {
+            buffer.push(create_entry(line), OversizedLinePolicy::TruncateToFit);
+        }
+        assert_eq!(lines(&buffer), ["oversized "]);
+        assert_eq!(buffer.oversized(), 0);
+
+        buffer.push(create_entry("ééééé-"), OversizedLinePolicy::TruncateToFit);
+        assert_eq!(lines(&buffer), ["ééééé"]);
+    }
+
+    #[test]
+    fn test_candid_roundtrip() {
+        let entry = LogEntry {
+            level: Level::Debug,
+            target: "my_crate::db".to_string(),
+            line: "[DEBUG my_crate::db] query".to_string(),
+        };
+
+        let bytes = candid::encode_one(&entry).unwrap();
+        assert_eq!(candid::decode_one::<LogEntry>(&bytes).unwrap(), entry);
+    }
+
+    fn create_entry(line: &str) -> LogEntry {
+        LogEntry {
+            level: Level::Warn,
+            target: "my_crate".to_string(),
+            line: line.to_string(),
+        }
+    }
+}
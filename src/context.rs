@@ -0,0 +1,115 @@
+//! Fields added to every line while they are set, e.g. the id of the request being handled.
+
+use std::cell::RefCell;
+use std::sync::{Mutex, PoisonError};
+
+/// The key of the field set by [`with_timer_context`].
+const TIMER_KEY: &str = "timer";
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Add a field to every line logged from now on, replacing the value of a field with the same key.
+///
+/// The fields follow the key-values of a record, in the order they were first set:
+///
+/// ```no_run
+/// #[ic_cdk::update]
+/// fn transfer(request_id: u64) {
+///     ic_logger::set_context_field("request_id", request_id);
+///     log::info!("transferring"); // [INFO  my_canister] transferring request_id=42
+///     ic_logger::clear_context_fields();
+/// }
+/// ```
+///
+/// A canister keeps its state between messages, so the fields stay set until they are cleared
+/// with [`clear_context_fields`].
+pub fn set_context_field(key: &str, value: impl ToString) {
+    CONTEXT.with_borrow_mut(|context| {
+        let value = value.to_string();
+        match context.iter_mut().find(|(existing, _value)| existing == key) {
+            Some((_key, existing)) => *existing = value,
+            None => context.push((key.to_string(), value)),
+        }
+    });
+}
+
+/// Run a timer callback with the id of the timer set as the `timer` context field.
+///
+/// Lines logged from timers don't tell which one fired, this tags them for the duration of the
+/// callback, the same as `set_context_field("timer", id)`:
+///
+/// ```no_run
+/// fn sweep() {
+///     log::info!("sweeping"); // [INFO  my_canister] sweeping timer=sweep
+/// }
+///
+/// #[ic_cdk::init]
+/// fn init() {
+///     ic_logger::init().unwrap();
+///     // e.g. with `ic_cdk_timers::set_timer_interval(Duration::from_secs(60), || ...)`
+///     ic_logger::with_timer_context("sweep", sweep);
+/// }
+/// ```
+///
+/// Afterwards the field is set back to its previous value, or removed if there was none. A
+/// panicking callback traps, which rolls back the fields along with all other state.
+pub fn with_timer_context<R>(timer: impl ToString, callback: impl FnOnce() -> R) -> R {
+    let previous = CONTEXT.with_borrow(|context| {
+        context
+            .iter()
+            .find(|(key, _value)| key == TIMER_KEY)
+            .map(|(_key, value)| value.clone())
+    });
+    set_context_field(TIMER_KEY, timer);
+    let result = callback();
+    match previous {
+        Some(previous) => set_context_field(TIMER_KEY, previous),
+        None => CONTEXT.with_borrow_mut(|context| context.retain(|(key, _value)| key != TIMER_KEY)),
+    }
+    result
+}
+
+/// Remove all fields set with [`set_context_field`].
+pub fn clear_context_fields() {
+    CONTEXT.with_borrow_mut(Vec::clear);
+}
+
+/// Generates the correlation ids of [`IcLogger::with_auto_correlation`](crate::IcLogger::with_auto_correlation).
+#[derive(Default)]
+pub(crate) struct Correlation {
+    /// The time the current id was generated at, how many ids were generated and the current id
+    state: Mutex<(Option<u64>, u64, u64)>,
+}
+
+impl Correlation {
+    /// The id of the call at `now`, a new one if the time changed since the last call.
+    pub(crate) fn id(&self, now: u64) -> String {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (time, count, id) = &mut *state;
+        if *time != Some(now) {
+            *time = Some(now);
+            *count += 1;
+            *id = mix(now ^ count.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        }
+        format!("{:08x}", *id as u32)
+    }
+
+    /// Make the next call to [`id`](Correlation::id) generate a new id.
+    pub(crate) fn reset(&self) {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).0 = None;
+    }
+}
+
+/// Scramble the bits of a value, the finalizer of SplitMix64.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// The fields currently set, without allocating if there are none.
+pub(crate) fn context_fields() -> Vec<(String, String)> {
+    CONTEXT.with_borrow(Vec::clone)
+}
@@ -0,0 +1,122 @@
+//! Collapsing of repeated records into summaries.
+
+use std::sync::{Mutex, PoisonError};
+
+use log::Level;
+
+/// A record that was let through and how many times it was repeated since.
+struct Last {
+    level: Level,
+    target: String,
+    message: String,
+    repeats: u64,
+}
+
+/// The repeats of a record that were dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Summary {
+    /// The level of the repeated record
+    pub(crate) level: Level,
+    /// The target of the repeated record
+    pub(crate) target: String,
+    /// How many times the record was repeated
+    pub(crate) repeats: u64,
+}
+
+impl Summary {
+    pub(crate) fn message(&self) -> String {
+        match self.repeats {
+            1 => "last message repeated 1 time".to_string(),
+            repeats => format!("last message repeated {repeats} times"),
+        }
+    }
+}
+
+/// What [`Dedup::check`] decided about a record.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Seen {
+    /// The record repeats the previous one and should be dropped
+    Repeat,
+    /// The record differs from the previous one, whose repeats may need a summary
+    New(Option<Summary>),
+}
+
+/// Collapses consecutive identical records.
+///
+/// Records are identical when their level, target and message match.
+pub(crate) struct Dedup {
+    last: Mutex<Option<Last>>,
+}
+
+impl Dedup {
+    pub(crate) fn new() -> Dedup {
+        Dedup { last: Mutex::new(None) }
+    }
+
+    pub(crate) fn check(&self, level: Level, target: &str, message: &str) -> Seen {
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(last) = last.as_mut() {
+            if last.level == level && last.target == target && last.message == message {
+                last.repeats += 1;
+                return Seen::Repeat;
+            }
+        }
+
+        let previous = last.replace(Last {
+            level,
+            target: target.to_string(),
+            message: message.to_string(),
+            repeats: 0,
+        });
+        Seen::New(previous.and_then(Last::summary))
+    }
+
+    /// Take the summary of the repeats of the last record, if there were any.
+    pub(crate) fn take_summary(&self) -> Option<Summary> {
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        let last = last.as_mut()?;
+        let summary = Summary {
+            level: last.level,
+            target: last.target.clone(),
+            repeats: std::mem::take(&mut last.repeats),
+        };
+        (summary.repeats > 0).then_some(summary)
+    }
+}
+
+impl Last {
+    fn summary(self) -> Option<Summary> {
+        (self.repeats > 0).then_some(Summary {
+            level: self.level,
+            target: self.target,
+            repeats: self.repeats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let dedup = Dedup::new();
+
+        assert_eq!(dedup.check(Level::Warn, "my_crate", "retrying"), Seen::New(None));
+        assert_eq!(dedup.check(Level::Warn, "my_crate", "retrying"), Seen::Repeat);
+        assert_eq!(dedup.check(Level::Warn, "my_crate", "retrying"), Seen::Repeat);
+        assert_eq!(
+            dedup.check(Level::Warn, "my_crate", "done"),
+            Seen::New(Some(Summary {
+                level: Level::Warn,
+                target: "my_crate".to_string(),
+                repeats: 2,
+            }))
+        );
+        assert_eq!(dedup.check(Level::Error, "my_crate", "done"), Seen::New(None));
+        assert_eq!(dedup.check(Level::Error, "my_crate", "done"), Seen::Repeat);
+        assert_eq!(dedup.take_summary().unwrap().repeats, 1);
+        assert_eq!(dedup.take_summary(), None);
+        assert_eq!(dedup.check(Level::Error, "my_crate", "done"), Seen::Repeat);
+    }
+}
@@ -0,0 +1,57 @@
+//! The errors of installing the logger.
+
+use std::fmt;
+
+use log::SetLoggerError;
+
+/// Why the logger couldn't be installed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An [`IcLogger`](crate::IcLogger) was already installed
+    ///
+    /// Use [`restore`](crate::restore) or [`set_floor_level`](crate::set_floor_level) to change the
+    /// levels of the installed logger instead.
+    AlreadyInitialized,
+    /// A different logger was already registered with the `log` crate
+    LoggerAlreadySet(SetLoggerError),
+    /// A directive of [`IcLogger::with_directives`](crate::IcLogger::with_directives) has a level
+    /// that is not a level name
+    ///
+    /// Use [`IcLogger::with_lenient_parsing`](crate::IcLogger::with_lenient_parsing) to fall back
+    /// to a level instead.
+    InvalidLevel(String),
+    /// More level rules were added than allowed by [`IcLogger::with_max_rules`](crate::IcLogger::with_max_rules)
+    TooManyRules {
+        /// The number of rules that were added
+        count: usize,
+        /// The maximum number of rules
+        max: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AlreadyInitialized => f.write_str("the ic logger was already initialized"),
+            Error::LoggerAlreadySet(error) => write!(f, "{error}"),
+            Error::InvalidLevel(token) => write!(f, "`{token}` is not a log level"),
+            Error::TooManyRules { count, max } => write!(f, "{count} level rules exceed the maximum of {max}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::AlreadyInitialized | Error::InvalidLevel(_) | Error::TooManyRules { .. } => None,
+            Error::LoggerAlreadySet(error) => Some(error),
+        }
+    }
+}
+
+impl From<SetLoggerError> for Error {
+    fn from(error: SetLoggerError) -> Self {
+        Error::LoggerAlreadySet(error)
+    }
+}
@@ -0,0 +1,393 @@
+//! Level filtering that only depends on `core` and `alloc`.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{Level, LevelFilter, Metadata};
+
+/// The next id handed to a filter, which tells the targets interned by different filters apart.
+static NEXT_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+/// A target registered with [`Filter::intern_target`].
+///
+/// Checking an interned target compares levels only, instead of matching the target against
+/// every module override. An id is only valid for the filter that interned it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TargetId {
+    owner: usize,
+    index: usize,
+}
+
+/// The default level and module overrides of a [`Filter`] at some point in time.
+///
+/// Taken with [`Filter::snapshot`] and reapplied with [`Filter::restore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    default_level: LevelFilter,
+    module_levels: Vec<(String, LevelFilter)>,
+    single: bool,
+}
+
+/// A default log level with overrides for specific modules and their sub-modules.
+#[derive(Debug)]
+pub struct Filter {
+    /// The default logging level
+    default_level: LevelFilter,
+
+    /// The specific logging level for each module
+    ///
+    /// This is used to override the default value for some specific modules.
+    /// The vector is kept sorted so that the first (prefix) match directly gives us the desired log level.
+    module_levels: Vec<(String, LevelFilter)>,
+
+    /// The interned targets, indexed by [`TargetId`], with the level that currently applies to them
+    interned: Vec<(String, LevelFilter)>,
+
+    /// Whether the only module override is matched by equality, see [`Filter::single_target`]
+    single: bool,
+
+    /// The id stored in the [`TargetId`]s interned by this filter
+    owner: usize,
+}
+
+impl Clone for Filter {
+    /// Clone the levels and interned targets, the clone doesn't accept the ids of the original.
+    fn clone(&self) -> Self {
+        Filter {
+            default_level: self.default_level,
+            module_levels: self.module_levels.clone(),
+            interned: self.interned.clone(),
+            single: self.single,
+            owner: next_owner(),
+        }
+    }
+}
+
+impl Filter {
+    /// Create a filter with the given default level and no module overrides.
+    pub fn new(default_level: LevelFilter) -> Filter {
+        Filter {
+            default_level,
+            module_levels: Vec::new(),
+            interned: Vec::new(),
+            single: false,
+            owner: next_owner(),
+        }
+    }
+
+    /// Create a filter that only lets records of exactly `target` through, up to `level`.
+    ///
+    /// Checking a record compares its target against a single string instead of scanning the
+    /// module overrides, and sub-modules of `target` are not matched. Changing the default level
+    /// or adding a module override turns it into a regular filter with `target` as its override.
+    pub fn single_target(target: &str, level: LevelFilter) -> Filter {
+        Filter {
+            default_level: LevelFilter::Off,
+            module_levels: alloc::vec![(target.to_string(), level)],
+            interned: Vec::new(),
+            single: true,
+            owner: next_owner(),
+        }
+    }
+
+    /// The level used for targets without a module override.
+    pub fn default_level(&self) -> LevelFilter {
+        self.default_level
+    }
+
+    /// The number of module overrides.
+    pub fn module_level_count(&self) -> usize {
+        self.module_levels.len()
+    }
+
+    /// Set the level used for targets without a module override.
+    pub fn set_default_level(&mut self, level: LevelFilter) {
+        self.default_level = level;
+        self.single = false;
+        self.resolve_interned();
+    }
+
+    /// Override the level of a module and all its sub-modules.
+    ///
+    /// When both the level for a parent module as well as a child module are set, the more
+    /// specific value is taken.
+    pub fn add_module_level(&mut self, target: &str, level: LevelFilter) {
+        self.module_levels.push((target.to_string(), level));
+        self.single = false;
+
+        /* Sort all module levels from most specific to least specific. The length of the module
+         * name is used instead of its actual depth to avoid module name parsing.
+         */
+        self.module_levels
+            .sort_by_key(|(name, _level)| name.len().wrapping_neg());
+        self.resolve_interned();
+    }
+
+    /// Combine the default level and module overrides of `other` into this filter.
+    ///
+    /// The more verbose of the two default levels is kept. Module overrides of `other` replace
+    /// the ones for the same target, overrides for other targets are kept from both filters.
+    pub fn merge(&mut self, other: &Filter) {
+        self.default_level = self.default_level.max(other.default_level);
+        for (target, level) in &other.module_levels {
+            self.module_levels.retain(|(name, _level)| name != target);
+            self.module_levels.push((target.clone(), *level));
+        }
+        self.module_levels
+            .sort_by_key(|(name, _level)| name.len().wrapping_neg());
+        self.single = false;
+        self.resolve_interned();
+    }
+
+    /// Take a snapshot of the default level and module overrides.
+    pub fn snapshot(&self) -> ConfigSnapshot {
+        ConfigSnapshot {
+            default_level: self.default_level,
+            module_levels: self.module_levels.clone(),
+            single: self.single,
+        }
+    }
+
+    /// Replace the default level and module overrides with the ones of a snapshot.
+    ///
+    /// Interned targets stay valid, their levels are resolved against the restored overrides.
+    pub fn restore(&mut self, snapshot: ConfigSnapshot) {
+        self.default_level = snapshot.default_level;
+        self.module_levels = snapshot.module_levels;
+        self.single = snapshot.single;
+        self.resolve_interned();
+    }
+
+    /// All rules, in the order they are matched against a target.
+    ///
+    /// The module overrides come first, from the most to the least specific one, followed by the
+    /// default level for the empty prefix, which matches every target.
+    pub fn rules(&self) -> Vec<(String, LevelFilter)> {
+        let mut rules = self.module_levels.clone();
+        rules.push((String::new(), self.default_level));
+        rules
+    }
+
+    /// The level that applies to the given target.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        if self.single {
+            let (name, level) = &self.module_levels[0];
+            return if name == target { *level } else { LevelFilter::Off };
+        }
+        self.module_levels
+            .iter()
+            /* The Vec is sorted so that we can simply take the first match */
+            .find(|(name, _level)| target.starts_with(name.as_str()))
+            .map(|(_name, level)| *level)
+            .unwrap_or(self.default_level)
+    }
+
+    /// The module override that applies to the given target, if any.
+    pub fn module_for(&self, target: &str) -> Option<&str> {
+        self.module_levels
+            .iter()
+            .map(|(name, _level)| name.as_str())
+            .find(|name| match self.single {
+                true => *name == target,
+                false => target.starts_with(name),
+            })
+    }
+
+    /// Check whether a record with the given metadata passes the filter.
+    pub fn enabled(&self, metadata: &Metadata) -> bool {
+        /* Levels are compared with filters directly, every level is more verbose than `Off`, so
+         * there is no need to convert them with `to_level_filter` first.
+         */
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    /// Register a target so that it can be checked with [`enabled_id`](Filter::enabled_id).
+    ///
+    /// Interning the same target again returns the same id. The level of an interned target is
+    /// resolved once and kept up to date when the filter changes.
+    pub fn intern_target(&mut self, target: &str) -> TargetId {
+        let index = match self.interned.iter().position(|(name, _level)| name == target) {
+            Some(index) => index,
+            None => {
+                self.interned.push((target.to_string(), self.level_for(target)));
+                self.interned.len() - 1
+            }
+        };
+        TargetId {
+            owner: self.owner,
+            index,
+        }
+    }
+
+    /// Take over the interned targets of `other`, so that the ids it handed out are valid for
+    /// this filter, resolved against the levels of this filter.
+    ///
+    /// The ids handed out by this filter before are no longer valid.
+    pub fn adopt_interned(&mut self, other: &Filter) {
+        self.interned = other.interned.clone();
+        self.owner = other.owner;
+        self.resolve_interned();
+    }
+
+    /// The target an id was interned for.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was interned by a different filter.
+    pub fn interned_target(&self, id: TargetId) -> &str {
+        &self.interned(id).0
+    }
+
+    /// The level that applies to an interned target.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was interned by a different filter.
+    pub fn level_for_id(&self, id: TargetId) -> LevelFilter {
+        self.interned(id).1
+    }
+
+    /// The interned target of an id and its level.
+    fn interned(&self, id: TargetId) -> &(String, LevelFilter) {
+        assert_eq!(id.owner, self.owner, "the target id was interned by a different filter");
+        &self.interned[id.index]
+    }
+
+    /// Check whether a record of an interned target at `level` passes the filter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was interned by a different filter.
+    pub fn enabled_id(&self, id: TargetId, level: Level) -> bool {
+        level <= self.level_for_id(id)
+    }
+
+    /// Resolve the levels of all interned targets again.
+    fn resolve_interned(&mut self) {
+        for index in 0..self.interned.len() {
+            self.interned[index].1 = self.level_for(&self.interned[index].0);
+        }
+    }
+
+    /// The most verbose level that any target may log at.
+    pub fn max_level(&self) -> LevelFilter {
+        self.module_levels
+            .iter()
+            .map(|(_name, level)| *level)
+            .fold(self.default_level, Ord::max)
+    }
+}
+
+/// Take the next filter id.
+fn next_owner() -> usize {
+    NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_level_for() {
+        let mut filter = Filter::new(LevelFilter::Warn);
+        filter.add_module_level("my_crate", LevelFilter::Info);
+        filter.add_module_level("my_crate::db", LevelFilter::Trace);
+
+        assert_eq!(filter.level_for("other_crate"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("my_crate"), LevelFilter::Info);
+        assert_eq!(filter.level_for("my_crate::api"), LevelFilter::Info);
+        assert_eq!(filter.level_for("my_crate::db::query"), LevelFilter::Trace);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+        assert!(filter.enabled(&Metadata::builder().target("my_crate").level(Level::Info).build()));
+        assert!(!filter.enabled(&Metadata::builder().target("my_crate").level(Level::Debug).build()));
+    }
+
+    #[test]
+    fn test_level_table() {
+        /* Rows are the levels of records, columns the filters from `Off` to `Trace` */
+        let table = [
+            (Level::Error, [false, true, true, true, true, true]),
+            (Level::Warn, [false, false, true, true, true, true]),
+            (Level::Info, [false, false, false, true, true, true]),
+            (Level::Debug, [false, false, false, false, true, true]),
+            (Level::Trace, [false, false, false, false, false, true]),
+        ];
+        for (level, row) in table {
+            for (filter, expected) in LevelFilter::iter().zip(row) {
+                let metadata = Metadata::builder().target("my_crate").level(level).build();
+                let mut filter = Filter::new(filter);
+                assert_eq!(
+                    filter.enabled(&metadata),
+                    expected,
+                    "{level} against {}",
+                    filter.default_level()
+                );
+
+                let id = filter.intern_target("my_crate");
+                assert_eq!(filter.enabled_id(id, level), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_target() {
+        let single = Filter::single_target("my_crate", LevelFilter::Info);
+        let mut general = Filter::new(LevelFilter::Off);
+        general.add_module_level("my_crate", LevelFilter::Info);
+
+        for target in ["my_crate", "other_crate", "my_crat"] {
+            assert_eq!(single.level_for(target), general.level_for(target));
+            for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+                let metadata = Metadata::builder().target(target).level(level).build();
+                assert_eq!(single.enabled(&metadata), general.enabled(&metadata));
+            }
+        }
+        assert_eq!(single.max_level(), general.max_level());
+        assert_eq!(single.rules(), general.rules());
+
+        /* Only the general filter matches sub-modules */
+        assert_eq!(single.level_for("my_crate::db"), LevelFilter::Off);
+        assert_eq!(single.module_for("my_crate::db"), None);
+        assert_eq!(general.module_for("my_crate::db"), Some("my_crate"));
+        assert_eq!(general.level_for("my_crate::db"), LevelFilter::Info);
+
+        /* A restored snapshot keeps matching by equality */
+        let mut restored = Filter::new(LevelFilter::Trace);
+        restored.restore(single.snapshot());
+        assert_eq!(restored.level_for("my_crate"), LevelFilter::Info);
+        assert_eq!(restored.level_for("my_crate::db"), LevelFilter::Off);
+    }
+
+    #[test]
+    fn test_intern_target() {
+        let targets = ["other_crate", "my_crate", "my_crate::api", "my_crate::db::query"];
+        let mut filter = Filter::new(LevelFilter::Warn);
+        let ids: Vec<_> = targets.iter().map(|target| filter.intern_target(target)).collect();
+        filter.add_module_level("my_crate", LevelFilter::Info);
+        filter.add_module_level("my_crate::db", LevelFilter::Trace);
+
+        assert_eq!(filter.intern_target("my_crate"), ids[1]);
+        assert_eq!(filter.interned_target(ids[2]), "my_crate::api");
+        for (target, id) in targets.iter().zip(&ids) {
+            for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+                let metadata = Metadata::builder().target(target).level(level).build();
+                assert_eq!(filter.enabled_id(*id, level), filter.enabled(&metadata));
+            }
+        }
+
+        let mut other = Filter::new(LevelFilter::Off);
+        let other_id = other.intern_target("my_crate::db::query");
+        other.adopt_interned(&filter);
+        assert_eq!(other.interned_target(ids[3]), "my_crate::db::query");
+        assert_eq!(other.level_for_id(ids[3]), LevelFilter::Off);
+        assert_ne!(other_id, ids[3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "interned by a different filter")]
+    fn test_foreign_target_id() {
+        let id = Filter::new(LevelFilter::Warn).intern_target("my_crate");
+        Filter::new(LevelFilter::Warn).level_for_id(id);
+    }
+}
@@ -0,0 +1,1101 @@
+//! Line formatting that only depends on `core` and `alloc`.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use log::kv::{Error, Key, Value, VisitSource, VisitValue};
+use log::{Level, Record};
+
+/// A column of a rendered line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// The time at which the record was logged, if timestamps are enabled
+    Timestamp,
+    /// The build version, if set
+    Version,
+    /// The static tag, if set
+    Tag,
+    /// The level of the record
+    Level,
+    /// The target of the record
+    Target,
+    /// The source file and line of the record, if enabled
+    Location,
+    /// The message of the record, followed by its key-values
+    Message,
+}
+
+/// The default order of columns, e.g. `[2023-07-27T23:08:09.718590904Z WARN  my_canister] message`.
+pub const DEFAULT_COLUMNS: [Column; 7] = [
+    Column::Timestamp,
+    Column::Version,
+    Column::Tag,
+    Column::Level,
+    Column::Target,
+    Column::Location,
+    Column::Message,
+];
+
+/// The overall shape of a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A readable line made of the configured columns, e.g. `[WARN  my_canister] message`
+    #[default]
+    Human,
+    /// A CSV record of the level, target and message, e.g. `WARN,my_canister,message`
+    ///
+    /// Fields containing commas, quotes or line breaks are quoted. The other columns don't apply.
+    Csv,
+    /// A JSON object, e.g.
+    /// `{"v":2,"ts":1690499289718590904,"level":"WARN","target":"my_canister","msg":"message"}`
+    ///
+    /// The keys always come in this order:
+    ///
+    /// - `v`: the version of the layout as a number, [`JSON_SCHEMA_VERSION`] unless overridden
+    ///   with [`LineFormat::json_schema_version`]
+    /// - `ts`: the timestamp in nanoseconds since the unix epoch as a number, only when enabled
+    /// - `version`: the [`LineFormat::version`] as a string, only when set
+    /// - `tag`: the [`LineFormat::tag`] as a string, only when set
+    /// - `level`: the level as a string, or its [`syslog_severity`] as a number if enabled
+    /// - `target`: the target as a string
+    /// - `msg`: the message as a string
+    /// - `causes`: the values of all [`CAUSES_KEY`] key-values as an array of strings, only when
+    ///   there are any, e.g. the chain of an error logged with [`log_error`](crate::log_error)
+    /// - `field_count`: the number of key-values of the record, only when enabled with
+    ///   [`LineFormat::json_field_count`]
+    /// - `fields`: the key-values of the record as an object, only when there are any. Integers,
+    ///   finite floats and booleans keep their JSON type, all other values are strings
+    ///
+    /// The other columns don't apply.
+    Json,
+    /// A [`Format::Json`] object meant to be an element of a JSON array
+    ///
+    /// Elements are always a single line without a separator of their own, so the lines kept by a
+    /// canister can be joined into a valid array with [`json_array`], e.g. by a query method.
+    JsonArrayElement,
+    /// A compact JSON object with a fixed layout, one per line, e.g.
+    /// `{"ts":1690499289718590904,"level":"WARN","target":"my_canister","msg":"message"}`
+    ///
+    /// The keys always come in this order, without any whitespace between them:
+    ///
+    /// - `ts`: the timestamp in nanoseconds since the unix epoch as a number, only when enabled
+    /// - `version`: the [`LineFormat::version`] as a string, only when set
+    /// - `tag`: the [`LineFormat::tag`] as a string, only when set
+    /// - `level`: the level as an upper case string
+    /// - `target`: the target as a string
+    /// - `msg`: the message as a string, line breaks are escaped
+    /// - `fields`: the key-values of the record as an object, only when there are any, with the
+    ///   same types as in [`Format::Json`]. The values of all [`CAUSES_KEY`] key-values are
+    ///   collected into a single `causes` array of strings
+    ///
+    /// Unlike [`Format::Json`], the options of [`LineFormat`] that change the JSON layout don't
+    /// apply, so the same record always renders to the same bytes. The other columns don't apply
+    /// either.
+    Ndjson,
+}
+
+/// How the timestamp column of [`Format::Human`] lines is rendered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// An RFC 3339 timestamp in UTC, e.g. `2023-07-27T23:08:09.718590904Z`
+    #[default]
+    Rfc3339,
+    /// The time elapsed since the logger was initialised, e.g. `+0.250s`
+    ///
+    /// The baseline is [`LineFormat::timestamp_baseline`].
+    SinceInit,
+}
+
+/// The version of the layout of [`Format::Json`] lines, rendered as their `v` key.
+///
+/// It's bumped whenever keys are renamed, removed or change their type, so consumers can tell
+/// the layouts apart.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+/// The key of key-values that [`Format::Json`] lines render as their `causes` array.
+///
+/// A record can have several key-values with this key, one per cause.
+pub const CAUSES_KEY: &str = "causes";
+
+/// The casing of a level name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Case {
+    /// `WARN`, like in the other formats
+    #[default]
+    Upper,
+    /// `warn`
+    Lower,
+}
+
+/// The header of [`Format::Csv`] lines.
+pub const CSV_HEADER: &str = "level,target,message";
+
+/// Common arrangements of the timestamp, level and target columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// `[2023-07-27T23:08:09.718590904Z WARN  my_canister] message`
+    #[default]
+    TimestampLevelTarget,
+    /// `[WARN  2023-07-27T23:08:09.718590904Z my_canister] message`
+    LevelTimestampTarget,
+    /// `[WARN  my_canister 2023-07-27T23:08:09.718590904Z] message`
+    LevelTargetTimestamp,
+}
+
+impl FieldOrder {
+    /// The column order of this arrangement.
+    pub fn columns(self) -> [Column; 7] {
+        match self {
+            FieldOrder::TimestampLevelTarget => DEFAULT_COLUMNS,
+            FieldOrder::LevelTimestampTarget => [
+                Column::Level,
+                Column::Timestamp,
+                Column::Version,
+                Column::Tag,
+                Column::Target,
+                Column::Location,
+                Column::Message,
+            ],
+            FieldOrder::LevelTargetTimestamp => [
+                Column::Level,
+                Column::Target,
+                Column::Timestamp,
+                Column::Version,
+                Column::Tag,
+                Column::Location,
+                Column::Message,
+            ],
+        }
+    }
+}
+
+/// Options controlling how a record is rendered into a line.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct LineFormat {
+    /// The overall shape of a line
+    pub kind: Format,
+
+    /// The order of the columns of a line
+    ///
+    /// The columns before the message are rendered as a bracketed prefix and the ones after it as a
+    /// bracketed suffix. Columns missing from the order are not rendered.
+    pub columns: Vec<Column>,
+
+    /// The build version tagged onto every line
+    pub version: Option<String>,
+
+    /// A static tag added to every line
+    pub tag: Option<String>,
+
+    /// Whether the source location of a record is rendered
+    pub location: bool,
+
+    /// Whether keys of a record's key-values are padded to a common width
+    pub kv_align: bool,
+
+    /// Whether a short hash of the message is appended to every line
+    pub message_hash: bool,
+
+    /// The casing of the level of [`Format::Json`] lines
+    pub json_level_case: Case,
+
+    /// The version rendered as the `v` key of [`Format::Json`] lines
+    pub json_schema_version: u32,
+
+    /// Whether only the last `::`-delimited segment of a target is rendered
+    pub short_target: bool,
+
+    /// Whether multiline, brace-delimited messages get tree-style guides, see [`pretty_debug`]
+    pub pretty_debug: bool,
+
+    /// How the timestamp column is rendered
+    pub timestamp_format: TimestampFormat,
+
+    /// The time [`TimestampFormat::SinceInit`] counts from, in nanoseconds since the unix epoch
+    pub timestamp_baseline: u64,
+
+    /// Whether levels are rendered as their [`syslog_severity`] instead of their name
+    pub syslog_severity: bool,
+
+    /// Whether [`Format::Json`] lines include the number of key-values of a record
+    pub json_field_count: bool,
+
+    /// Whether trailing whitespace is trimmed from messages
+    pub trim: bool,
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        LineFormat {
+            kind: Format::Human,
+            columns: DEFAULT_COLUMNS.to_vec(),
+            version: None,
+            tag: None,
+            location: false,
+            kv_align: false,
+            message_hash: false,
+            json_level_case: Case::Upper,
+            json_schema_version: JSON_SCHEMA_VERSION,
+            short_target: false,
+            pretty_debug: false,
+            timestamp_format: TimestampFormat::Rfc3339,
+            timestamp_baseline: 0,
+            syslog_severity: false,
+            json_field_count: false,
+            trim: false,
+        }
+    }
+}
+
+impl LineFormat {
+    /// Render a record into a line.
+    ///
+    /// The `timestamp`, in nanoseconds since the unix epoch, is only rendered when given.
+    pub fn format(&self, record: &Record, timestamp: Option<u64>) -> String {
+        self.format_as(self.kind, record, timestamp)
+    }
+
+    /// Render a record into a line of the given `kind`, ignoring [`kind`](#structfield.kind).
+    ///
+    /// This lets the same options render a record in several formats, e.g. for sinks with a format
+    /// of their own.
+    pub fn format_as(&self, kind: Format, record: &Record, timestamp: Option<u64>) -> String {
+        let target = if !record.target().is_empty() {
+            record.target()
+        } else {
+            record.module_path().unwrap_or_default()
+        };
+        let target = match self.short_target {
+            true => target.rsplit("::").next().unwrap_or(target),
+            false => target,
+        };
+
+        match kind {
+            Format::Human => self.format_human(record, target, timestamp),
+            Format::Csv => format!(
+                "{},{},{}",
+                self.level(record.level()),
+                csv_field(target),
+                csv_field(&self.message(record))
+            ),
+            Format::Json | Format::JsonArrayElement => self.format_json(record, target, timestamp),
+            Format::Ndjson => self.format_ndjson(record, target, timestamp),
+        }
+    }
+
+    /// Render a record as a JSON object with the fixed layout of [`Format::Ndjson`].
+    fn format_ndjson(&self, record: &Record, target: &str, timestamp: Option<u64>) -> String {
+        /* The order of the keys is part of the documented layout, keep it in sync with `Format::Ndjson` */
+        let mut json = String::from("{");
+        if let Some(timestamp) = timestamp {
+            json.push_str(&format!("\"ts\":{timestamp},"));
+        }
+        self.push_json_labels(&mut json);
+        json.push_str(&format!(
+            "\"level\":{},\"target\":{},\"msg\":{}",
+            json_string(record.level().as_str()),
+            json_string(target),
+            json_string(&self.message(record))
+        ));
+
+        let mut fields = JsonFields::default();
+        /* Collecting into strings can't fail, so there is no error to handle */
+        let _ = record.key_values().visit(&mut fields);
+        if !fields.causes.is_empty() {
+            let causes = format!("{}:[{}]", json_string(CAUSES_KEY), fields.causes.join(","));
+            fields.fields.push(causes);
+        }
+        if !fields.fields.is_empty() {
+            json.push_str(&format!(",\"fields\":{{{}}}", fields.fields.join(",")));
+        }
+
+        json.push('}');
+        json
+    }
+
+    /// Render a record as a JSON object.
+    fn format_json(&self, record: &Record, target: &str, timestamp: Option<u64>) -> String {
+        let level = match (self.syslog_severity, self.json_level_case) {
+            (true, _) => syslog_severity(record.level()).to_string(),
+            (false, Case::Upper) => json_string(record.level().as_str()),
+            (false, Case::Lower) => json_string(&record.level().as_str().to_ascii_lowercase()),
+        };
+        /* The order of the keys is part of the documented layout, keep it in sync with `Format::Json` */
+        let mut json = format!("{{\"v\":{},", self.json_schema_version);
+        if let Some(timestamp) = timestamp {
+            json.push_str(&format!("\"ts\":{timestamp},"));
+        }
+        self.push_json_labels(&mut json);
+        json.push_str(&format!(
+            "\"level\":{},\"target\":{},\"msg\":{}",
+            level,
+            json_string(target),
+            json_string(&self.message(record))
+        ));
+
+        let mut fields = JsonFields::default();
+        /* Collecting into strings can't fail, so there is no error to handle */
+        let _ = record.key_values().visit(&mut fields);
+        if !fields.causes.is_empty() {
+            json.push_str(&format!(",\"causes\":[{}]", fields.causes.join(",")));
+        }
+        if self.json_field_count {
+            json.push_str(&format!(",\"field_count\":{}", fields.fields.len()));
+        }
+        if !fields.fields.is_empty() {
+            json.push_str(&format!(",\"fields\":{{{}}}", fields.fields.join(",")));
+        }
+
+        json.push('}');
+        json
+    }
+
+    /// Append the `version` and `tag` keys of a JSON object, if set.
+    fn push_json_labels(&self, json: &mut String) {
+        if let Some(version) = &self.version {
+            json.push_str(&format!("\"version\":{},", json_string(version)));
+        }
+        if let Some(tag) = &self.tag {
+            json.push_str(&format!("\"tag\":{},", json_string(tag)));
+        }
+    }
+
+    /// The message of a record, without trailing whitespace if trimming is enabled.
+    fn message(&self, record: &Record) -> String {
+        let mut message = record.args().to_string();
+        if self.trim {
+            message.truncate(message.trim_end().len());
+        }
+        message
+    }
+
+    /// The name of a level, or its syslog severity if enabled.
+    fn level(&self, level: Level) -> String {
+        match self.syslog_severity {
+            true => syslog_severity(level).to_string(),
+            false => level.to_string(),
+        }
+    }
+
+    fn format_human(&self, record: &Record, target: &str, timestamp: Option<u64>) -> String {
+        let (mut prefix, mut suffix) = (Vec::new(), Vec::new());
+        let mut after_message = false;
+        for column in &self.columns {
+            let value = match column {
+                Column::Timestamp => timestamp.map(|timestamp| match self.timestamp_format {
+                    TimestampFormat::Rfc3339 => format_timestamp(timestamp),
+                    TimestampFormat::SinceInit => format_elapsed(timestamp.saturating_sub(self.timestamp_baseline)),
+                }),
+                Column::Version => self.version.clone(),
+                Column::Tag => self.tag.clone(),
+                Column::Level => Some(format!("{:<5}", self.level(record.level()))),
+                Column::Target => Some(target.to_string()),
+                Column::Location => match (self.location, record.file(), record.line()) {
+                    (true, Some(file), Some(line)) => Some(format!("{file}:{line}")),
+                    (true, Some(file), None) => Some(file.to_string()),
+                    _ => None,
+                },
+                Column::Message => {
+                    after_message = true;
+                    None
+                }
+            };
+            match value {
+                Some(value) if after_message => suffix.push(value),
+                Some(value) => prefix.push(value),
+                None => {}
+            }
+        }
+
+        let message = self.message(record);
+        /* The message is borrowed, it's only copied once into the joined line */
+        let shown = match self.pretty_debug.then(|| pretty_debug(&message)).flatten() {
+            Some(pretty) => Cow::Owned(pretty),
+            None => Cow::Borrowed(message.as_str()),
+        };
+        /* Parts are separated by single spaces, an empty message is left out instead of leaving
+         * a gap between the prefix and the key-values.
+         */
+        let mut parts: Vec<Cow<'_, str>> = Vec::new();
+        if !prefix.is_empty() {
+            parts.push(format!("[{}]", prefix.join(" ")).into());
+        }
+        if !shown.is_empty() {
+            parts.push(shown);
+        }
+
+        let mut key_values = KeyValues::default();
+        /* Collecting into strings can't fail, so there is no error to handle */
+        let _ = record.key_values().visit(&mut key_values);
+        let width = if self.kv_align {
+            key_values
+                .0
+                .iter()
+                .map(|(key, _value)| key.len())
+                .max()
+                .unwrap_or_default()
+        } else {
+            0
+        };
+        for (key, value) in &key_values.0 {
+            parts.push(format!("{key:<width$}={value}").into());
+        }
+
+        if self.message_hash {
+            parts.push(format!("#{:08x}", message_hash(&message)).into());
+        }
+
+        if !suffix.is_empty() {
+            parts.push(format!("[{}]", suffix.join(" ")).into());
+        }
+
+        parts.join(" ")
+    }
+}
+
+/// The severity of a level as defined by [RFC 5424](https://www.rfc-editor.org/rfc/rfc5424#section-6.2.1).
+///
+/// Levels map to `Error` (3), `Warning` (4), `Informational` (6) and `Debug` (7), there are no
+/// levels for the other severities:
+///
+/// ```
+/// use log::Level;
+/// use ic_logger::format::syslog_severity;
+///
+/// assert_eq!(syslog_severity(Level::Warn), 4);
+/// assert_eq!(syslog_severity(Level::Trace), 7);
+/// ```
+pub fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Join lines of [`Format::JsonArrayElement`] into a JSON array.
+///
+/// ```
+/// let elements = [r#"{"v":2,"level":"WARN"}"#, r#"{"v":2,"level":"INFO"}"#];
+/// assert_eq!(ic_logger::format::json_array(elements), r#"[{"v":2,"level":"WARN"},{"v":2,"level":"INFO"}]"#);
+/// ```
+pub fn json_array<I>(elements: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut array = String::from("[");
+    for (index, element) in elements.into_iter().enumerate() {
+        if index > 0 {
+            array.push(',');
+        }
+        array.push_str(element.as_ref());
+    }
+    array.push(']');
+    array
+}
+
+/// Format a duration in nanoseconds as seconds with millisecond precision, e.g. `+0.250s`.
+fn format_elapsed(nanos: u64) -> String {
+    format!("+{}.{:03}s", nanos / 1_000_000_000, nanos % 1_000_000_000 / 1_000_000)
+}
+
+/// Format nanoseconds since the unix epoch as an RFC 3339 timestamp in UTC.
+pub fn format_timestamp(nanos: u64) -> String {
+    let secs = nanos / 1_000_000_000;
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    /* Convert days since the epoch to a civil date, see
+     * http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+     */
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:09}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        nanos % 1_000_000_000
+    )
+}
+
+/// Add tree-style guides to a multiline message delimited by braces, brackets or parentheses.
+///
+/// This is meant for pretty-printed `{:#?}` output, whose continuation lines are hard to tell
+/// apart from other log lines:
+///
+/// ```text
+/// State {
+///   │     users: 3,
+///   └ }
+/// ```
+///
+/// Returns `None` for any other message.
+pub fn pretty_debug(message: &str) -> Option<String> {
+    let (first, rest) = message.split_once('\n')?;
+    let close = match first.trim_end().chars().last()? {
+        '{' => '}',
+        '[' => ']',
+        '(' => ')',
+        _ => return None,
+    };
+    let (middle, last) = rest.rsplit_once('\n').unwrap_or(("", rest));
+    if !last.trim_start().starts_with(close) {
+        return None;
+    }
+
+    let mut pretty = first.to_string();
+    for line in middle.lines() {
+        pretty.push_str("\n  │ ");
+        pretty.push_str(line);
+    }
+    pretty.push_str("\n  └ ");
+    pretty.push_str(last);
+    Some(pretty)
+}
+
+/// Quote and escape a JSON string.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if u32::from(c) < 0x20 => json.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Quote a CSV field if needed, as described in RFC 4180.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Hash a message with 32-bit FNV-1a.
+pub fn message_hash(message: &str) -> u32 {
+    message.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Collects the key-values of a record as rendered strings.
+#[derive(Default)]
+struct KeyValues(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for KeyValues {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Collects the key-values of a record as JSON members, apart from the [`CAUSES_KEY`] ones.
+#[derive(Default)]
+struct JsonFields {
+    fields: Vec<String>,
+    causes: Vec<String>,
+}
+
+impl<'kvs> VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        if key.as_str() == CAUSES_KEY {
+            self.causes.push(json_string(&value.to_string()));
+            return Ok(());
+        }
+        let mut json = JsonValue::default();
+        value.visit(&mut json)?;
+        self.fields.push(format!("{}:{}", json_string(key.as_str()), json.0));
+        Ok(())
+    }
+}
+
+/// Renders a value with its native JSON type.
+///
+/// Integers, finite floats and booleans become JSON numbers and booleans, everything else is
+/// rendered as a string.
+#[derive(Default)]
+struct JsonValue(String);
+
+impl<'v> VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: Value) -> Result<(), Error> {
+        self.0 = json_string(&value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), Error> {
+        self.0 = "null".to_string();
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), Error> {
+        self.0 = value.to_string();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), Error> {
+        self.0 = value.to_string();
+        Ok(())
+    }
+
+    fn visit_u128(&mut self, value: u128) -> Result<(), Error> {
+        self.0 = value.to_string();
+        Ok(())
+    }
+
+    fn visit_i128(&mut self, value: i128) -> Result<(), Error> {
+        self.0 = value.to_string();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), Error> {
+        /* JSON has no representation for NaN and the infinities */
+        self.0 = match value.is_finite() {
+            true => value.to_string(),
+            false => json_string(&value.to_string()),
+        };
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), Error> {
+        self.0 = value.to_string();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_columns() {
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("my_crate")
+            .file(Some("src/lib.rs"))
+            .line(Some(42))
+            .args(format_args!("message"))
+            .build();
+        let mut format = LineFormat {
+            tag: Some("payments".to_string()),
+            location: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format.format(&record, Some(0)),
+            "[1970-01-01T00:00:00.000000000Z payments WARN  my_crate src/lib.rs:42] message"
+        );
+
+        format.columns = [
+            Column::Level,
+            Column::Target,
+            Column::Message,
+            Column::Location,
+            Column::Timestamp,
+        ]
+        .to_vec();
+        assert_eq!(
+            format.format(&record, Some(0)),
+            "[WARN  my_crate] message [src/lib.rs:42 1970-01-01T00:00:00.000000000Z]"
+        );
+
+        format.columns = [Column::Message, Column::Tag].to_vec();
+        assert_eq!(format.format(&record, None), "message [payments]");
+    }
+
+    #[test]
+    fn test_csv() {
+        let format = LineFormat {
+            kind: Format::Csv,
+            ..Default::default()
+        };
+        let line = |message: &str| {
+            format.format(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("my_crate")
+                    .args(format_args!("{message}"))
+                    .build(),
+                Some(0),
+            )
+        };
+
+        assert_eq!(line("plain message"), "WARN,my_crate,plain message");
+        assert_eq!(line("a, b and c"), "WARN,my_crate,\"a, b and c\"");
+        assert_eq!(line("say \"hi\""), "WARN,my_crate,\"say \"\"hi\"\"\"");
+        assert_eq!(line("two\nlines"), "WARN,my_crate,\"two\nlines\"");
+    }
+
+    #[test]
+    fn test_json() {
+        let format = LineFormat {
+            kind: Format::Json,
+            ..Default::default()
+        };
+        let key_values = [("id", "7")];
+        let record = Record::builder()
+            .level(Level::Info)
+            .target("my_crate")
+            .args(format_args!("say \"hi\"\n\u{1}"))
+            .key_values(&key_values)
+            .build();
+
+        assert_eq!(
+            format.format(&record, Some(42)),
+            r#"{"v":2,"ts":42,"level":"INFO","target":"my_crate","msg":"say \"hi\"\n\u0001","fields":{"id":"7"}}"#
+        );
+
+        let lowercase = LineFormat {
+            json_level_case: Case::Lower,
+            ..format
+        };
+        assert_eq!(
+            lowercase.format(&record, None),
+            r#"{"v":2,"level":"info","target":"my_crate","msg":"say \"hi\"\n\u0001","fields":{"id":"7"}}"#
+        );
+    }
+
+    #[test]
+    fn test_json_field_types() {
+        let format = LineFormat {
+            kind: Format::Json,
+            ..Default::default()
+        };
+        let record = |key_values: &[(&str, Value)]| {
+            format.format(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target("my_crate")
+                    .args(format_args!("message"))
+                    .key_values(&key_values)
+                    .build(),
+                None,
+            )
+        };
+        let fields = |line: String| line.split_once(",\"fields\":").unwrap().1.to_string();
+
+        assert_eq!(
+            fields(record(&[("count", Value::from(3u32)), ("delta", Value::from(-2i64))])),
+            r#"{"count":3,"delta":-2}}"#
+        );
+        assert_eq!(
+            fields(record(&[("ok", Value::from(true)), ("ratio", Value::from(0.5))])),
+            r#"{"ok":true,"ratio":0.5}}"#
+        );
+        assert_eq!(
+            fields(record(&[("user", Value::from("alice")), ("id", Value::from("7"))])),
+            r#"{"user":"alice","id":"7"}}"#
+        );
+        assert_eq!(
+            fields(record(&[("ratio", Value::from(f64::NAN))])),
+            r#"{"ratio":"NaN"}}"#
+        );
+    }
+
+    #[test]
+    fn test_syslog_severity() {
+        let levels = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+        let severities: Vec<_> = levels.into_iter().map(syslog_severity).collect();
+        assert_eq!(severities, [3, 4, 6, 7, 7]);
+
+        let format = LineFormat {
+            syslog_severity: true,
+            ..LineFormat::default()
+        };
+        let render = |kind, level| {
+            format.format_as(
+                kind,
+                &Record::builder()
+                    .args(format_args!("message"))
+                    .level(level)
+                    .target("my_crate")
+                    .build(),
+                None,
+            )
+        };
+        assert_eq!(render(Format::Human, Level::Warn), "[4     my_crate] message");
+        assert_eq!(render(Format::Csv, Level::Error), "3,my_crate,message");
+        assert_eq!(
+            render(Format::Json, Level::Info),
+            r#"{"v":2,"level":6,"target":"my_crate","msg":"message"}"#
+        );
+    }
+
+    #[test]
+    fn test_json_field_count() {
+        let format = LineFormat {
+            kind: Format::Json,
+            json_field_count: true,
+            ..LineFormat::default()
+        };
+        let render = |key_values: &[(&str, u64)]| {
+            format.format(
+                &Record::builder()
+                    .args(format_args!("message"))
+                    .level(Level::Info)
+                    .target("my_crate")
+                    .key_values(&key_values)
+                    .build(),
+                None,
+            )
+        };
+
+        assert_eq!(
+            render(&[]),
+            r#"{"v":2,"level":"INFO","target":"my_crate","msg":"message","field_count":0}"#
+        );
+        assert_eq!(
+            render(&[("id", 7)]),
+            r#"{"v":2,"level":"INFO","target":"my_crate","msg":"message","field_count":1,"fields":{"id":7}}"#
+        );
+        assert_eq!(
+            render(&[("id", 7), ("size", 3), ("age", 1)]),
+            r#"{"v":2,"level":"INFO","target":"my_crate","msg":"message","field_count":3,"fields":{"id":7,"size":3,"age":1}}"#
+        );
+    }
+
+    #[test]
+    fn test_json_causes() {
+        let key_values = [(CAUSES_KEY, "first"), ("id", "7"), (CAUSES_KEY, "second")];
+        let record = Record::builder()
+            .args(format_args!("failed"))
+            .level(Level::Error)
+            .target("my_crate")
+            .key_values(&key_values)
+            .build();
+
+        assert_eq!(
+            LineFormat::default().format_as(Format::Json, &record, None),
+            r#"{"v":2,"level":"ERROR","target":"my_crate","msg":"failed","causes":["first","second"],"fields":{"id":"7"}}"#
+        );
+    }
+
+    #[test]
+    fn test_trim() {
+        let render = |trim, kind| {
+            let format = LineFormat {
+                trim,
+                ..LineFormat::default()
+            };
+            format.format_as(
+                kind,
+                &Record::builder()
+                    .args(format_args!("  padded message \t\n"))
+                    .level(Level::Warn)
+                    .target("my_crate")
+                    .key_values(&[("id", 7)])
+                    .build(),
+                None,
+            )
+        };
+
+        assert_eq!(
+            render(false, Format::Human),
+            "[WARN  my_crate]   padded message \t\n id=7"
+        );
+        assert_eq!(render(true, Format::Human), "[WARN  my_crate]   padded message id=7");
+        assert_eq!(render(true, Format::Csv), "WARN,my_crate,  padded message");
+        assert_eq!(
+            render(true, Format::Json),
+            r#"{"v":2,"level":"WARN","target":"my_crate","msg":"  padded message","fields":{"id":7}}"#
+        );
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let format = LineFormat {
+            kind: Format::Ndjson,
+            json_field_count: true,
+            json_level_case: Case::Lower,
+            syslog_severity: true,
+            ..LineFormat::default()
+        };
+        let record = Record::builder()
+            .args(format_args!("say \"hi\"\nagain"))
+            .level(Level::Warn)
+            .target("my_crate")
+            .key_values(&[("id", 7)])
+            .build();
+
+        assert_eq!(
+            format.format(&record, Some(42)),
+            r#"{"ts":42,"level":"WARN","target":"my_crate","msg":"say \"hi\"\nagain","fields":{"id":7}}"#
+        );
+        assert_eq!(
+            format.format(
+                &Record::builder()
+                    .args(format_args!("plain"))
+                    .level(Level::Warn)
+                    .target("my_crate")
+                    .build(),
+                None
+            ),
+            r#"{"level":"WARN","target":"my_crate","msg":"plain"}"#
+        );
+    }
+
+    #[test]
+    fn test_json_array() {
+        let format = LineFormat {
+            kind: Format::JsonArrayElement,
+            ..Default::default()
+        };
+        let elements: Vec<_> = ["first", "second\nline", "third"]
+            .iter()
+            .map(|message| {
+                format.format(
+                    &Record::builder()
+                        .level(Level::Warn)
+                        .target("my_crate")
+                        .args(format_args!("{message}"))
+                        .build(),
+                    None,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            json_array(&elements),
+            concat!(
+                r#"[{"v":2,"level":"WARN","target":"my_crate","msg":"first"},"#,
+                r#"{"v":2,"level":"WARN","target":"my_crate","msg":"second\nline"},"#,
+                r#"{"v":2,"level":"WARN","target":"my_crate","msg":"third"}]"#
+            )
+        );
+        assert_eq!(json_array(&elements[..1]), format!("[{}]", elements[0]));
+        assert_eq!(json_array(Vec::<String>::new()), "[]");
+    }
+
+    #[test]
+    fn test_json_key_order() {
+        let format = LineFormat {
+            kind: Format::Json,
+            ..Default::default()
+        };
+        let key_values = [("id", "7")];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("my_crate")
+            .args(format_args!("message"))
+            .key_values(&key_values)
+            .build();
+
+        let json = format.format(&record, Some(42));
+        let positions: Vec<_> = [
+            "\"v\":2,",
+            "\"ts\":42,",
+            "\"level\":",
+            "\"target\":",
+            "\"msg\":",
+            "\"fields\":",
+        ]
+        .iter()
+        .map(|key| json.find(key).unwrap())
+        .collect();
+        assert!(json.starts_with("{\"v\":2,"));
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_empty_message() {
+        let key_values = [("user", "alice"), ("action", "login")];
+        let with_fields = Record::builder()
+            .level(Level::Info)
+            .target("my_crate")
+            .args(format_args!(""))
+            .key_values(&key_values)
+            .build();
+        let plain = Record::builder()
+            .level(Level::Info)
+            .target("my_crate")
+            .args(format_args!(""))
+            .build();
+
+        let format = LineFormat::default();
+        assert_eq!(
+            format.format(&with_fields, None),
+            "[INFO  my_crate] user=alice action=login"
+        );
+        assert_eq!(format.format(&plain, None), "[INFO  my_crate]");
+
+        let format = LineFormat {
+            columns: alloc::vec![Column::Message],
+            ..Default::default()
+        };
+        assert_eq!(format.format(&with_fields, None), "user=alice action=login");
+    }
+
+    #[test]
+    fn test_pretty_debug() {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        struct State {
+            users: u32,
+            admins: Vec<&'static str>,
+        }
+        let state = State {
+            users: 3,
+            admins: alloc::vec!["alice"],
+        };
+        let format = LineFormat {
+            pretty_debug: true,
+            ..Default::default()
+        };
+        let line = format.format(
+            &Record::builder()
+                .level(Level::Debug)
+                .target("my_crate")
+                .args(format_args!("{state:#?}"))
+                .build(),
+            None,
+        );
+
+        assert_eq!(
+            line,
+            "[DEBUG my_crate] State {\n  │     users: 3,\n  │     admins: [\n  │         \"alice\",\n  │     ],\n  └ }"
+        );
+        assert_eq!(pretty_debug("State { users: 3 }"), None);
+        assert_eq!(pretty_debug("first\nsecond"), None);
+        assert_eq!(pretty_debug("[\n]").as_deref(), Some("[\n  └ ]"));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00.000000000Z");
+        assert_eq!(
+            format_timestamp(951_782_400_000_000_001),
+            "2000-02-29T00:00:00.000000001Z"
+        );
+        assert_eq!(
+            format_timestamp(1_690_499_289_718_590_904),
+            "2023-07-27T23:08:09.718590904Z"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Forwarding of log entries to a collector canister.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use candid::Principal;
+use ic_cdk::api::call::RejectionCode;
+use log::Level;
+
+use crate::buffer::LogEntry;
+
+/// Ships every logged entry to a method of a collector canister.
+///
+/// Entries are sent as a Candid-encoded [`LogEntry`] with a one-way call, so logging never waits
+/// for the collector.
+pub(crate) struct Forwarder {
+    collector: Principal,
+    method: String,
+
+    /// Sends an encoded argument to a method of a canister
+    pub(crate) send: fn(Principal, &str, &[u8]) -> Result<(), RejectionCode>,
+}
+
+impl Forwarder {
+    pub(crate) fn new(collector: Principal, method: &str) -> Forwarder {
+        Forwarder {
+            collector,
+            method: method.to_string(),
+            send: notify,
+        }
+    }
+
+    /// Send `entry` to the collector, describing the failure if the call couldn't be made.
+    pub(crate) fn forward(&self, entry: &LogEntry) -> Result<(), String> {
+        let arg = candid::encode_one(entry).map_err(|error| format!("failed to encode a log entry: {error}"))?;
+        (self.send)(self.collector, &self.method, &arg).map_err(|code| {
+            format!(
+                "failed to forward a log entry to {}.{}: {code:?}",
+                self.collector, self.method
+            )
+        })
+    }
+}
+
+/// Lets identical entries through at most once per interval.
+///
+/// Entries are identical when their level, target and message match.
+pub(crate) struct Throttle {
+    /// The interval in nanoseconds
+    interval: u64,
+
+    /// When each distinct entry was last let through
+    last_allowed: Mutex<HashMap<(Level, String, String), u64>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(interval: u64) -> Throttle {
+        Throttle {
+            interval,
+            last_allowed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether an entry may pass at `now`, in nanoseconds since the unix epoch.
+    pub(crate) fn allow(&self, level: Level, target: &str, message: &str, now: u64) -> bool {
+        let mut last_allowed = self.last_allowed.lock().unwrap_or_else(PoisonError::into_inner);
+        /* Forget about the entries whose interval is over, so the map doesn't grow unbounded */
+        last_allowed.retain(|_key, at| now.saturating_sub(*at) < self.interval);
+
+        let key = (level, target.to_string(), message.to_string());
+        if last_allowed.contains_key(&key) {
+            return false;
+        }
+        last_allowed.insert(key, now);
+        true
+    }
+}
+
+/// Make a one-way call, outside of a canister there is nobody to call.
+fn notify(canister: Principal, method: &str, arg: &[u8]) -> Result<(), RejectionCode> {
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::call::notify_raw(canister, method, arg, 0)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::Level;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static SENT: RefCell<Vec<(Principal, String, Vec<u8>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(10);
+
+        assert!(throttle.allow(Level::Error, "my_crate", "failed", 100));
+        assert!(!throttle.allow(Level::Error, "my_crate", "failed", 105));
+        assert!(!throttle.allow(Level::Error, "my_crate", "failed", 109));
+        assert!(throttle.allow(Level::Warn, "my_crate", "failed", 109));
+        assert!(throttle.allow(Level::Error, "other_crate", "failed", 109));
+        assert!(throttle.allow(Level::Error, "my_crate", "failed again", 109));
+        assert!(throttle.allow(Level::Error, "my_crate", "failed", 110));
+        assert!(!throttle.allow(Level::Error, "my_crate", "failed", 119));
+    }
+
+    #[test]
+    fn test_forward() {
+        let mut forwarder = Forwarder::new(Principal::anonymous(), "ingest");
+        forwarder.send = |canister, method, arg| {
+            SENT.with(|sent| sent.borrow_mut().push((canister, method.to_string(), arg.to_vec())));
+            Ok(())
+        };
+        let entry = LogEntry {
+            level: Level::Error,
+            target: "my_crate".to_string(),
+            line: "[ERROR my_crate] failed".to_string(),
+        };
+
+        assert_eq!(forwarder.forward(&entry), Ok(()));
+
+        let sent = SENT.with(|sent| sent.take());
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, Principal::anonymous());
+        assert_eq!(sent[0].1, "ingest");
+        assert_eq!(candid::decode_one::<LogEntry>(&sent[0].2).unwrap(), entry);
+
+        forwarder.send = |_, _, _| Err(RejectionCode::DestinationInvalid);
+        assert_eq!(
+            forwarder.forward(&entry),
+            Err("failed to forward a log entry to 2vxsx-fae.ingest: DestinationInvalid".to_string())
+        );
+    }
+}
@@ -3,218 +3,87 @@
 //! Just initialize logging without any configuration:
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! ic_logger::init().unwrap();
 //! log::warn!("This is an example message.");
+//! # }
 //! ```
 //!
 //! Hardcode a default log level:
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! ic_logger::init_with_level(log::Level::Warn).unwrap();
+//! # }
 //! ```
-
-use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
-
-/// Implements [`Log`] and a set of simple builder methods for configuration.
-///
-/// Use the various "builder" methods on this struct to configure the logger,
-/// then call [`init`] to configure the [`log`] crate.
-pub struct IcLogger {
-    /// The default logging level
-    default_level: LevelFilter,
-
-    /// The specific logging level for each module
-    ///
-    /// This is used to override the default value for some specific modules.
-    /// After initialization, the vector is sorted so that the first (prefix) match
-    /// directly gives us the desired log level.
-    module_levels: Vec<(String, LevelFilter)>,
-}
-
-impl IcLogger {
-    /// Initializes the global logger with a IcLogger instance with
-    /// default log level set to `Level::Warn`.
-    ///
-    /// ```no_run
-    /// use ic_logger::IcLogger;
-    /// IcLogger::new().init().unwrap();
-    /// log::warn!("This is an example message.");
-    /// ```
-    ///
-    /// [`init`]: #method.init
-    #[must_use = "You must call init() to begin logging"]
-    pub fn new() -> IcLogger {
-        IcLogger {
-            default_level: LevelFilter::Warn,
-            module_levels: Vec::new(),
-        }
-    }
-
-    /// Set the 'default' log level.
-    ///
-    /// You can override the default level for specific modules and their sub-modules using [`with_module_level`]
-    ///
-    /// [`with_module_level`]: #method.with_module_level
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_level(mut self, level: LevelFilter) -> IcLogger {
-        self.default_level = level;
-        self
-    }
-
-    /// Override the log level for some specific modules.
-    ///
-    /// This sets the log level of a specific module and all its sub-modules.
-    /// When both the level for a parent module as well as a child module are set,
-    /// the more specific value is taken. If the log level for the same module is
-    /// specified twice, the resulting log level is implementation defined.
-    ///
-    /// # Examples
-    ///
-    /// Silence an overly verbose crate:
-    ///
-    /// ```no_run
-    /// use ic_logger::IcLogger;
-    /// use log::LevelFilter;
-    ///
-    /// IcLogger::new().with_module_level("chatty_dependency", LevelFilter::Warn).init().unwrap();
-    /// ```
-    ///
-    /// Disable logging for all dependencies:
-    ///
-    /// ```no_run
-    /// use ic_logger::IcLogger;
-    /// use log::LevelFilter;
-    ///
-    /// IcLogger::new()
-    ///     .with_level(LevelFilter::Off)
-    ///     .with_module_level("my_crate", LevelFilter::Info)
-    ///     .init()
-    ///     .unwrap();
-    /// ```
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_module_level(mut self, target: &str, level: LevelFilter) -> IcLogger {
-        self.module_levels.push((target.to_string(), level));
-
-        /* Normally this is only called in `init` to avoid redundancy, but we can't initialize the logger in tests */
-        #[cfg(test)]
-        self.module_levels
-            .sort_by_key(|(name, _level)| name.len().wrapping_neg());
-
-        self
-    }
-
-    /// 'Init' the actual logger, instantiate it and configure it,
-    /// this method MUST be called in order for the logger to be effective.
-    pub fn init(mut self) -> Result<(), SetLoggerError> {
-        /* Sort all module levels from most specific to least specific. The length of the module
-         * name is used instead of its actual depth to avoid module name parsing.
-         */
-        self.module_levels
-            .sort_by_key(|(name, _level)| name.len().wrapping_neg());
-        let max_level = self.module_levels.iter().map(|(_name, level)| level).copied().max();
-        let max_level = max_level
-            .map(|lvl| lvl.max(self.default_level))
-            .unwrap_or(self.default_level);
-        log::set_max_level(max_level);
-        log::set_boxed_logger(Box::new(self))?;
-        Ok(())
-    }
-}
-
-impl Default for IcLogger {
-    /// See [this](struct.IcLogger.html#method.new)
-    fn default() -> Self {
-        IcLogger::new()
-    }
-}
-
-impl Log for IcLogger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        &metadata.level().to_level_filter()
-            <= self
-                .module_levels
-                .iter()
-                /* At this point the Vec is already sorted so that we can simply take
-                 * the first match
-                 */
-                .find(|(name, _level)| metadata.target().starts_with(name))
-                .map(|(_name, level)| level)
-                .unwrap_or(&self.default_level)
-    }
-
-    fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            let level_string = format!("{:<5}", record.level().to_string());
-
-            let target = if !record.target().is_empty() {
-                record.target()
-            } else {
-                record.module_path().unwrap_or_default()
-            };
-
-            ic_cdk::println!("[{level_string} {target}] {}", record.args());
-        }
-    }
-
-    fn flush(&self) {}
-}
-
-/// Initialise the logger with its default configuration.
-///
-/// Log messages will not be filtered.
-/// The `RUST_LOG` environment variable is not used.
-pub fn init() -> Result<(), SetLoggerError> {
-    IcLogger::new().init()
-}
-
-/// Initialise the logger with a specific log level.
-///
-/// Log messages below the given [`Level`] will be filtered.
-/// The `RUST_LOG` environment variable is not used.
-pub fn init_with_level(level: Level) -> Result<(), SetLoggerError> {
-    IcLogger::new().with_level(level.to_level_filter()).init()
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn test_module_levels_allowlist() {
-        let logger = IcLogger::new()
-            .with_level(LevelFilter::Off)
-            .with_module_level("my_crate", LevelFilter::Info);
-
-        assert!(logger.enabled(&create_log("my_crate", Level::Info)));
-        assert!(logger.enabled(&create_log("my_crate::module", Level::Info)));
-        assert!(!logger.enabled(&create_log("my_crate::module", Level::Debug)));
-        assert!(!logger.enabled(&create_log("not_my_crate", Level::Debug)));
-        assert!(!logger.enabled(&create_log("not_my_crate::module", Level::Error)));
-    }
-
-    #[test]
-    fn test_module_levels_denylist() {
-        let logger = IcLogger::new()
-            .with_level(LevelFilter::Debug)
-            .with_module_level("my_crate", LevelFilter::Trace)
-            .with_module_level("chatty_dependency", LevelFilter::Info);
-
-        assert!(logger.enabled(&create_log("my_crate", Level::Info)));
-        assert!(logger.enabled(&create_log("my_crate", Level::Trace)));
-        assert!(logger.enabled(&create_log("my_crate::module", Level::Info)));
-        assert!(logger.enabled(&create_log("my_crate::module", Level::Trace)));
-        assert!(logger.enabled(&create_log("not_my_crate", Level::Debug)));
-        assert!(!logger.enabled(&create_log("not_my_crate::module", Level::Trace)));
-        assert!(logger.enabled(&create_log("chatty_dependency", Level::Info)));
-        assert!(!logger.enabled(&create_log("chatty_dependency", Level::Debug)));
-        assert!(!logger.enabled(&create_log("chatty_dependency::module", Level::Debug)));
-        assert!(logger.enabled(&create_log("chatty_dependency::module", Level::Warn)));
-    }
-
-    fn create_log(name: &str, level: Level) -> Metadata {
-        let mut builder = Metadata::builder();
-        builder.level(level);
-        builder.target(name);
-        builder.build()
-    }
+//!
+//! The buffered lines are read with free functions like `get_logs` and `export_chunks`. They
+//! can be called at any time: before the logger is installed, e.g. while `IcLogger::init` is
+//! still running, they return nothing instead of panicking.
+//!
+//! Records can also be compiled out with the `max_level_*` and `release_max_level_*` features of
+//! the [`log`] crate. The logger respects [`log::STATIC_MAX_LEVEL`], so it never reports a
+//! statically disabled level as enabled, whatever levels it is configured with.
+//!
+//! Without the default `std` feature the crate is `no_std` and only provides the [`filter`] and
+//! [`format`](mod@format) modules, which implement the filtering and formatting logic on top of `alloc`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod buffer;
+#[cfg(feature = "std")]
+mod context;
+#[cfg(feature = "std")]
+mod dedup;
+#[cfg(feature = "std")]
+mod error;
+pub mod filter;
+pub mod format;
+#[cfg(feature = "std")]
+mod forward;
+#[cfg(feature = "std")]
+mod limit;
+#[cfg(feature = "std")]
+mod logger;
+#[cfg(feature = "std")]
+mod macros;
+#[cfg(feature = "std")]
+mod sequence;
+#[cfg(feature = "std")]
+mod sink;
+#[cfg(feature = "std")]
+mod span;
+#[cfg(feature = "std")]
+pub mod testing;
+
+#[cfg(feature = "std")]
+pub use buffer::{LogEntry, OversizedLinePolicy};
+#[cfg(feature = "std")]
+pub use context::{clear_context_fields, set_context_field, with_timer_context};
+#[cfg(feature = "std")]
+pub use error::Error;
+pub use filter::{ConfigSnapshot, TargetId};
+pub use format::{Case, Column, FieldOrder, Format, TimestampFormat, JSON_SCHEMA_VERSION};
+#[cfg(feature = "std")]
+pub use logger::{
+    capture, count_entries, describe, drain, ensure_installed, export_chunks, export_chunks_newest_first,
+    flush_on_heartbeat, get_entries_newest_first, get_logs, get_logs_text, get_named_logs, init, init_from_str,
+    init_with_level, intern_target, is_initialized, last_entry_at_level, log_bytes, log_error, log_once, log_to,
+    oversized_count, quick, rate_limited_count, reset_correlation_id, restore, restore_logs, seen_targets,
+    set_floor_level, set_suppressed, snapshot, suppressed_count, tail, would_buffer, would_log, would_log_id, Backend,
+    IcLogger, Preset, DEFAULT_BUFFER_CAPACITY, FORCE_PREFIX, INSTRUCTION_LIMIT, SEEN_TARGETS_LIMIT,
+};
+#[cfg(feature = "std")]
+pub use sequence::SequenceReset;
+#[cfg(feature = "std")]
+pub use sink::{Sink, Stream, STDERR_MARKER};
+#[cfg(feature = "std")]
+pub use span::{span, SpanGuard, SPAN_TARGET};
+
+#[doc(hidden)]
+pub mod __private {
+    pub use log::{Level, LevelFilter};
 }
@@ -0,0 +1,165 @@
+//! Limiting the number of records and bytes written.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Lets at most `max` records through per fixed window of time.
+pub(crate) struct RateLimiter {
+    max: u64,
+
+    /// The length of a window in nanoseconds
+    window: u64,
+
+    /// When the current window started and how many records it let through
+    current: Mutex<(u64, u64)>,
+
+    /// How many records were dropped, over all windows
+    dropped: AtomicU64,
+
+    /// How many records were dropped since the last [`RateLimiter::take_unreported`]
+    unreported: AtomicU64,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(max: u64, window: u64) -> RateLimiter {
+        RateLimiter {
+            max,
+            window,
+            current: Mutex::new((0, 0)),
+            dropped: AtomicU64::new(0),
+            unreported: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether a record may pass at `now`, in nanoseconds since the unix epoch.
+    pub(crate) fn allow(&self, now: u64) -> bool {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        let (start, count) = &mut *current;
+        if now.saturating_sub(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+        if *count < self.max {
+            *count += 1;
+            return true;
+        }
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.unreported.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// How many records were dropped since the last call, to be reported in a summary.
+    pub(crate) fn take_unreported(&self) -> u64 {
+        self.unreported.swap(0, Ordering::Relaxed)
+    }
+
+    /// How many records were dropped so far.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Lets the first `first` records through, then only every `then_every`th one.
+pub(crate) struct Sampler {
+    first: u64,
+    then_every: u64,
+
+    /// How many records were seen so far
+    seen: AtomicU64,
+}
+
+impl Sampler {
+    pub(crate) fn new(first: u64, then_every: u64) -> Sampler {
+        Sampler {
+            first,
+            then_every,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether the next record may pass.
+    pub(crate) fn allow(&self) -> bool {
+        let index = self.seen.fetch_add(1, Ordering::Relaxed);
+        match index.checked_sub(self.first) {
+            None => true,
+            Some(after) => self.then_every > 0 && (after + 1) % self.then_every == 0,
+        }
+    }
+}
+
+/// Stops logging once `max` bytes were written in total.
+pub(crate) struct OutputBudget {
+    max: u64,
+
+    /// How many bytes were written so far
+    used: AtomicU64,
+
+    /// Whether the notice that the budget is exhausted was written
+    notified: AtomicBool,
+}
+
+impl OutputBudget {
+    pub(crate) fn new(max: u64) -> OutputBudget {
+        OutputBudget {
+            max,
+            used: AtomicU64::new(0),
+            notified: AtomicBool::new(false),
+        }
+    }
+
+    /// The number of bytes that may be written in total.
+    pub(crate) fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Account for `bytes` written.
+    pub(crate) fn spend(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Whether the budget is used up, so nothing may be written anymore.
+    pub(crate) fn exhausted(&self) -> bool {
+        self.used.load(Ordering::Relaxed) >= self.max
+    }
+
+    /// Whether the notice that the budget is exhausted is still to be written, only true once.
+    pub(crate) fn take_notice(&self) -> bool {
+        !self.notified.swap(true, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_allow() {
+        let limiter = RateLimiter::new(2, 10);
+
+        assert!(limiter.allow(100));
+        assert!(limiter.allow(101));
+        assert!(!limiter.allow(109));
+        assert!(limiter.allow(110));
+        assert!(limiter.allow(115));
+        assert!(!limiter.allow(119));
+        assert!(!limiter.allow(119));
+        assert_eq!(limiter.dropped(), 3);
+        assert_eq!(limiter.take_unreported(), 3);
+        assert_eq!(limiter.take_unreported(), 0);
+    }
+
+    #[test]
+    fn test_sampler() {
+        let allowed = |sampler: Sampler| (0..10).map(|_| sampler.allow()).collect::<Vec<_>>();
+
+        assert_eq!(
+            allowed(Sampler::new(3, 3)),
+            [true, true, true, false, false, true, false, false, true, false]
+        );
+        assert_eq!(allowed(Sampler::new(0, 1)), [true; 10]);
+        assert_eq!(
+            allowed(Sampler::new(2, 0)),
+            [true, true, false, false, false, false, false, false, false, false]
+        );
+    }
+}
@@ -0,0 +1,5093 @@
+//! The canister logger and its installation as the global logger.
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use candid::Principal;
+use log::kv::{self, Key, Source, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::buffer::{Buffer, LogEntry, OversizedLinePolicy};
+use crate::context::{context_fields, Correlation};
+use crate::dedup::{Dedup, Seen, Summary};
+use crate::error::Error;
+use crate::filter::{ConfigSnapshot, Filter, TargetId};
+use crate::format::{
+    json_string, Case, Column, FieldOrder, Format, LineFormat, TimestampFormat, CAUSES_KEY, CSV_HEADER, DEFAULT_COLUMNS,
+};
+use crate::forward::{Forwarder, Throttle};
+use crate::limit::{OutputBudget, RateLimiter, Sampler};
+use crate::sequence::{Sequence, SequenceReset};
+use crate::sink::{Sink, Stream, STDERR_MARKER};
+
+thread_local! {
+    /// The logger installed by [`IcLogger::init`].
+    ///
+    /// Canisters run on a single thread, so this is effectively a global. Keeping it thread local
+    /// lets tests running on separate threads install their own loggers.
+    static LOGGER: RefCell<Option<IcLogger>> = const { RefCell::new(None) };
+}
+
+/// The capacity of the buffer enabled by [`IcLogger::with_console_and_buffer`], in lines.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 1000;
+
+/// The number of instructions an update message may execute, used by
+/// [`IcLogger::with_instructions_remaining`].
+pub const INSTRUCTION_LIMIT: u64 = 20_000_000_000;
+
+/// The number of distinct targets remembered by [`seen_targets`].
+pub const SEEN_TARGETS_LIMIT: usize = 256;
+
+/// The number of nanoseconds in a day.
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+
+thread_local! {
+    /// Whether the warning about trace logging in a production build was already written.
+    static TRACE_WARNED: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether a logger was successfully installed with [`IcLogger::init`].
+    ///
+    /// Thread local like `LOGGER`, which it describes, unlike `REGISTERED`, which is global
+    /// because the `log` crate only accepts one logger per process.
+    static INSTALLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether [`INSTALLED_LOGGER`] was successfully registered with the `log` crate.
+static REGISTERED: OnceLock<bool> = OnceLock::new();
+
+/// The logger registered with the `log` crate, forwarding to the installed [`IcLogger`].
+static INSTALLED_LOGGER: InstalledLogger = InstalledLogger;
+
+/// The prefix of targets whose records are logged regardless of the configured levels.
+///
+/// The prefix is stripped before the record is written, so a call site can force a single record
+/// through without changing any configuration:
+///
+/// ```no_run
+/// use ic_logger::IcLogger;
+/// use log::LevelFilter;
+///
+/// IcLogger::new()
+///     .with_level(LevelFilter::Info)
+///     .with_module_level("my_canister::db", LevelFilter::Warn)
+///     .init()
+///     .unwrap();
+///
+/// // logged as `[INFO  my_canister::db] ...` although `my_canister::db` is set to `Warn`
+/// log::info!(target: "force:my_canister::db", "slow query took {} instructions", 42);
+/// ```
+///
+/// Forced records are still subject to the max level of the `log` macros, which is the most
+/// verbose level the logger is configured with, see [`log::max_level`].
+pub const FORCE_PREFIX: &str = "force:";
+
+/// A callback registered with [`IcLogger::on_error`].
+type ErrorCallback = Box<dyn Fn(&str) + Send + Sync>;
+
+/// A predicate registered with [`IcLogger::with_filter_fn`].
+type MetadataFilter = Box<dyn Fn(&Metadata) -> bool + Send + Sync>;
+
+/// A level registered with [`IcLogger::with_module_level_fn`].
+type LevelFn = Box<dyn Fn() -> LevelFilter + Send + Sync>;
+
+/// A callback registered with [`IcLogger::with_line_interceptor`].
+type LineInterceptor = Box<dyn Fn(String) -> Option<String> + Send + Sync>;
+
+/// Where the logger writes its lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Print the line like [`ic_cdk::println`] does, to the debug output of the canister or to
+    /// stdout outside of a canister
+    #[default]
+    Println,
+    /// Hand the already formatted line to [`ic_cdk::api::print`], skipping the formatting machinery
+    /// of [`ic_cdk::println`]
+    ///
+    /// Lines are always printed this way, so this is the same as [`Backend::Println`].
+    RawPrint,
+}
+
+impl Backend {
+    /// Write a formatted line with `print`, which writes to the output of the canister.
+    fn print(self, line: &str, print: fn(&str)) {
+        /* `ic_cdk::println` would copy the line into a new string first, no backend needs that */
+        match self {
+            Backend::Println | Backend::RawPrint => print(line),
+        }
+    }
+}
+
+/// A coherent group of formatting options, see [`IcLogger::with_preset`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Just the level, target and message: `[WARN  my_canister] message`
+    Compact,
+    /// Everything about a record:
+    /// `[2023-07-27T23:08:09.718590904Z WARN  my_canister src/lib.rs:42] message`
+    Verbose,
+    /// One JSON object per line:
+    /// `{"v":2,"ts":1690499289718590904,"level":"WARN","target":"my_canister","msg":"message"}`
+    Json,
+}
+
+/// Implements [`Log`] and a set of simple builder methods for configuration.
+///
+/// Use the various "builder" methods on this struct to configure the logger,
+/// then call [`init`] to configure the [`log`] crate.
+pub struct IcLogger {
+    /// The default logging level and the specific logging level for each module
+    filter: Filter,
+
+    /// Records at or above this level are logged regardless of `filter`
+    passthrough: LevelFilter,
+
+    /// Whether all records are dropped, see [`set_suppressed`]
+    muted: bool,
+
+    /// Records at or above this level are logged regardless of `filter`, see [`set_floor_level`]
+    floor: LevelFilter,
+
+    /// Target prefixes whose records are logged regardless of `filter`
+    always_log: Vec<String>,
+
+    /// Target prefixes whose records are dropped unless they carry key-values
+    require_kv: Vec<String>,
+
+    /// The level that replaces unknown level names of directives, if parsing is lenient
+    lenient_fallback: Option<LevelFilter>,
+
+    /// The targets, or `None` for the default level, and level names of invalid directives
+    invalid_directives: Vec<(Option<String>, String)>,
+
+    /// The number of module level rules that may be added at most, if limited
+    max_rules: Option<usize>,
+
+    /// The directives that were parsed with the lenient fallback
+    parse_warnings: Vec<String>,
+
+    /// A predicate that records must satisfy on top of all other filters, if set
+    filter_fn: Option<MetadataFilter>,
+
+    /// Levels of the targets starting with a prefix, evaluated for every record
+    level_fns: Vec<(String, LevelFn)>,
+
+    /// How records are rendered into lines
+    format: LineFormat,
+
+    /// Whether every line is prefixed with a timestamp
+    timestamps: bool,
+
+    /// Target prefixes whose lines are prefixed with a timestamp regardless of `timestamps`
+    timestamp_targets: Vec<String>,
+
+    /// The source of timestamps, in nanoseconds since the unix epoch
+    clock: fn() -> u64,
+
+    /// The source of the number of instructions the current message executed
+    instruction_counter: fn() -> u64,
+
+    /// Where lines are written
+    backend: Backend,
+
+    /// Writes a line to the output of the canister
+    print: fn(&str),
+
+    /// Transforms or drops every formatted line, if set
+    interceptor: Option<LineInterceptor>,
+
+    /// Additional destinations, each with its own format or the one of `format` if none
+    sinks: Vec<(Box<dyn Sink>, Option<Format>)>,
+
+    /// Sinks bound to target prefixes, with whether matching records also go to the other outputs
+    target_sinks: Vec<(String, Box<dyn Sink>, bool)>,
+
+    /// Whether errors and warnings are written to the error stream
+    split_streams: bool,
+
+    /// The size of stable memory in bytes, added to every line if set
+    stable_size: Option<fn() -> u64>,
+
+    /// The instruction limit of a message, the instructions left before it are added to every line
+    /// if set
+    instructions_remaining: Option<u64>,
+
+    /// A daily window, in nanoseconds since midnight UTC, and the level records need within it
+    quiet_window: Option<(u64, u64, LevelFilter)>,
+
+    /// Identifies the installed instance of the logger, added to every line if set
+    session: Option<u64>,
+
+    /// Tags the lines of a call with a generated id, if enabled
+    correlation: Option<Correlation>,
+
+    /// Whether exports of the buffer start with a header describing them
+    export_header: bool,
+
+    /// Whether invalid UTF-8 in messages logged as bytes becomes replacement characters
+    lossy_utf8: bool,
+
+    /// Numbers every written record, if enabled
+    sequence: Option<Sequence>,
+
+    /// Whether the CSV header is printed when the logger is initialised
+    csv_header: bool,
+
+    /// Whether records logged during query calls are dropped
+    update_only: bool,
+
+    /// Whether the current message is a query call
+    in_query: fn() -> bool,
+
+    /// Records are dropped while the cycle balance is below this, if set
+    cycles_gate: Option<u128>,
+
+    /// The cycle balance of the canister
+    balance: fn() -> u128,
+
+    /// The most recent lines, if buffering is enabled
+    buffer: Option<Mutex<Buffer>>,
+
+    /// Buffers of their own for the targets starting with a prefix
+    named_buffers: Vec<(String, Mutex<Buffer>)>,
+
+    /// What the buffer does with lines longer than its byte cap
+    oversized_line_policy: OversizedLinePolicy,
+
+    /// The most verbose level that is printed, captured or batched
+    console_level: LevelFilter,
+
+    /// The most verbose level that is kept in the buffer
+    buffer_level: LevelFilter,
+
+    /// Lines waiting to be printed by the next flush, if batching is enabled
+    pending: Option<Mutex<Vec<String>>>,
+
+    /// Records at or above this level flush the batched lines right away, if set
+    batch_flush_level: Option<Level>,
+
+    /// Where entries are shipped to, if anywhere
+    forwarder: Option<Forwarder>,
+
+    /// Limits how often identical entries are forwarded
+    forward_throttle: Option<Throttle>,
+
+    /// Called with a description of every failure to deliver a line, see [`IcLogger::on_error`]
+    error_callbacks: Vec<ErrorCallback>,
+
+    /// Lines captured instead of being printed, with the level of their record, see [`capture`]
+    captured: Mutex<Option<Vec<(Level, String)>>>,
+
+    /// The keys already logged with [`log_once`]
+    once_keys: Mutex<HashSet<String>>,
+
+    /// Limits how many records are written per interval, if set
+    rate_limit: Option<RateLimiter>,
+
+    /// Samples the records of the targets starting with a prefix
+    samplers: Vec<(String, Sampler)>,
+
+    /// Stops logging once the written lines add up to a number of bytes, if set
+    output_budget: Option<OutputBudget>,
+
+    /// Splits messages longer than a number of bytes into several lines, if set
+    chunk_size: Option<usize>,
+
+    /// The number of records that reached the logger but were filtered out
+    suppressed: AtomicU64,
+
+    /// The targets of all records that reached the logger, up to [`SEEN_TARGETS_LIMIT`]
+    seen_targets: Mutex<BTreeSet<String>>,
+
+    /// Collapses repeated records, if enabled
+    dedup: Option<Dedup>,
+
+    /// The level of summaries like "last message repeated 5 times" and "3 records dropped by the
+    /// rate limit"
+    ///
+    /// Defaults to the level of the summarized record.
+    summary_level: Option<Level>,
+}
+
+impl IcLogger {
+    /// Initializes the global logger with a IcLogger instance with
+    /// default log level set to `Level::Warn`.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// IcLogger::new().init().unwrap();
+    /// log::warn!("This is an example message.");
+    /// ```
+    ///
+    /// [`init`]: #method.init
+    #[must_use = "You must call init() to begin logging"]
+    pub fn new() -> IcLogger {
+        IcLogger {
+            filter: Filter::new(LevelFilter::Warn),
+            passthrough: LevelFilter::Off,
+            muted: false,
+            floor: LevelFilter::Off,
+            always_log: Vec::new(),
+            require_kv: Vec::new(),
+            lenient_fallback: None,
+            invalid_directives: Vec::new(),
+            max_rules: None,
+            parse_warnings: Vec::new(),
+            filter_fn: None,
+            level_fns: Vec::new(),
+            format: LineFormat::default(),
+            timestamps: false,
+            timestamp_targets: Vec::new(),
+            clock: time,
+            instruction_counter,
+            backend: Backend::Println,
+            print: debug_print,
+            interceptor: None,
+            sinks: Vec::new(),
+            target_sinks: Vec::new(),
+            split_streams: false,
+            stable_size: None,
+            instructions_remaining: None,
+            quiet_window: None,
+            session: None,
+            correlation: None,
+            export_header: false,
+            lossy_utf8: false,
+            sequence: None,
+            csv_header: false,
+            update_only: false,
+            in_query: in_query_call,
+            cycles_gate: None,
+            balance: canister_balance,
+            buffer: None,
+            named_buffers: Vec::new(),
+            oversized_line_policy: OversizedLinePolicy::DropAndCount,
+            console_level: LevelFilter::Trace,
+            buffer_level: LevelFilter::Trace,
+            pending: None,
+            batch_flush_level: None,
+            forwarder: None,
+            forward_throttle: None,
+            error_callbacks: Vec::new(),
+            captured: Mutex::new(None),
+            once_keys: Mutex::new(HashSet::new()),
+            rate_limit: None,
+            samplers: Vec::new(),
+            output_budget: None,
+            chunk_size: None,
+            suppressed: AtomicU64::new(0),
+            seen_targets: Mutex::new(BTreeSet::new()),
+            dedup: None,
+            summary_level: None,
+        }
+    }
+
+    /// Set the 'default' log level.
+    ///
+    /// You can override the default level for specific modules and their sub-modules using [`with_module_level`]
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_level(mut self, level: LevelFilter) -> IcLogger {
+        self.filter.set_default_level(level);
+        self
+    }
+
+    /// Make the default level one step more verbose, e.g. from `Warn` to `Info`.
+    ///
+    /// Like a `-v` flag, this can be repeated, up to [`LevelFilter::Trace`]. Combined with
+    /// [`quiet`], it maps a verbosity count from the canister arguments onto the default level.
+    ///
+    /// [`quiet`]: #method.quiet
+    #[must_use = "You must call init() to begin logging"]
+    pub fn verbose(self) -> IcLogger {
+        let current = self.filter.default_level();
+        let level = LevelFilter::iter().find(|level| *level > current).unwrap_or(current);
+        self.with_level(level)
+    }
+
+    /// Make the default level one step less verbose, e.g. from `Warn` to `Error`.
+    ///
+    /// Like a `-q` flag, this can be repeated, down to [`LevelFilter::Off`], see [`verbose`].
+    ///
+    /// [`verbose`]: #method.verbose
+    #[must_use = "You must call init() to begin logging"]
+    pub fn quiet(self) -> IcLogger {
+        let current = self.filter.default_level();
+        let level = LevelFilter::iter()
+            .filter(|level| *level < current)
+            .last()
+            .unwrap_or(current);
+        self.with_level(level)
+    }
+
+    /// Set the default level by its index, from `0` for [`LevelFilter::Off`] to `5` for
+    /// [`LevelFilter::Trace`].
+    ///
+    /// This maps a position of a slider, e.g. in an admin UI, onto the default level. Indices past
+    /// the end are clamped to [`LevelFilter::Trace`].
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_level_index(self, index: usize) -> IcLogger {
+        let level = LevelFilter::iter()
+            .take(index.saturating_add(1))
+            .last()
+            .unwrap_or(LevelFilter::Off);
+        self.with_level(level)
+    }
+
+    /// Override the log level for some specific modules.
+    ///
+    /// This sets the log level of a specific module and all its sub-modules.
+    /// When both the level for a parent module as well as a child module are set,
+    /// the more specific value is taken. If the log level for the same module is
+    /// specified twice, the resulting log level is implementation defined.
+    ///
+    /// # Examples
+    ///
+    /// Silence an overly verbose crate:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new().with_module_level("chatty_dependency", LevelFilter::Warn).init().unwrap();
+    /// ```
+    ///
+    /// Disable logging for all dependencies:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new()
+    ///     .with_level(LevelFilter::Off)
+    ///     .with_module_level("my_crate", LevelFilter::Info)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_level(mut self, target: &str, level: LevelFilter) -> IcLogger {
+        self.filter.add_module_level(target, level);
+        self
+    }
+
+    /// Limit the number of module level rules to `max`.
+    ///
+    /// This guards against rules piling up when they are added programmatically, e.g. one per
+    /// request in a loop. Both the rules of [`with_module_level`], including the ones of
+    /// [`with_directives`], and the ones of [`with_module_level_fn`] count, the default level
+    /// doesn't. Adding more rules makes [`init`] fail with [`Error::TooManyRules`].
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    /// [`with_directives`]: #method.with_directives
+    /// [`with_module_level_fn`]: #method.with_module_level_fn
+    /// [`init`]: #method.init
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_max_rules(mut self, max: usize) -> IcLogger {
+        self.max_rules = Some(max);
+        self
+    }
+
+    /// Set the level of a module and its sub-modules to the one `f` returns whenever they log.
+    ///
+    /// Unlike [`with_module_level`], the level can follow external state, e.g. a feature flag kept
+    /// in stable memory, without touching the logger:
+    ///
+    /// ```no_run
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// static DEBUG_PAYMENTS: AtomicBool = AtomicBool::new(false);
+    ///
+    /// IcLogger::new()
+    ///     .with_module_level_fn("my_canister::payments", || match DEBUG_PAYMENTS.load(Ordering::Relaxed) {
+    ///         true => LevelFilter::Debug,
+    ///         false => LevelFilter::Warn,
+    ///     })
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Like module levels, the most specific one of these and the module levels applies to a
+    /// target, and it takes precedence over a module level set for the same target. Setting it
+    /// again for a target replaces the previous function. The max level of `log` can't follow the
+    /// function, so with any of them every record reaches the logger to be checked.
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_level_fn(
+        mut self,
+        target: &str,
+        f: impl Fn() -> LevelFilter + Send + Sync + 'static,
+    ) -> IcLogger {
+        self.level_fns.retain(|(prefix, _f)| prefix != target);
+        self.level_fns.push((target.to_string(), Box::new(f)));
+        self.level_fns.sort_by_key(|(prefix, _f)| prefix.len().wrapping_neg());
+        self
+    }
+
+    /// Set the default and module levels from a comma separated list of directives.
+    ///
+    /// A directive is either a level, which sets the default level like [`with_level`], or a
+    /// `target=level` pair, which sets the level of a module like [`with_module_level`]. Level names
+    /// are case insensitive, which suits configuration read from an external source:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_directives("warn,my_canister=info,my_canister::db=trace").init().unwrap();
+    /// ```
+    ///
+    /// A directive with an unknown level name makes [`init`] fail with [`Error::InvalidLevel`],
+    /// unless [`with_lenient_parsing`] is used.
+    ///
+    /// [`with_level`]: #method.with_level
+    /// [`with_module_level`]: #method.with_module_level
+    /// [`init`]: #method.init
+    /// [`with_lenient_parsing`]: #method.with_lenient_parsing
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_directives(mut self, spec: &str) -> IcLogger {
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let (target, token) = match directive.split_once('=') {
+                Some((target, token)) => (Some(target.trim()), token.trim()),
+                None => (None, directive),
+            };
+            match token.parse() {
+                Ok(level) => self.apply_directive(target, level),
+                Err(_) => self
+                    .invalid_directives
+                    .push((target.map(str::to_string), token.to_string())),
+            }
+        }
+        self.resolve_invalid_directives();
+        self
+    }
+
+    /// Replace unknown level names of [`with_directives`] with `fallback` instead of failing [`init`].
+    ///
+    /// This keeps a canister bootable despite a typo in its configuration. Every replaced level is
+    /// recorded in [`parse_warnings`] and logged as a warning once the logger is installed. It
+    /// applies to the directives given before and after it.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new()
+    ///     .with_lenient_parsing(LevelFilter::Info)
+    ///     .with_directives("warn,my_canister=inof")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_directives`]: #method.with_directives
+    /// [`init`]: #method.init
+    /// [`parse_warnings`]: #method.parse_warnings
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_lenient_parsing(mut self, fallback: LevelFilter) -> IcLogger {
+        self.lenient_fallback = Some(fallback);
+        self.resolve_invalid_directives();
+        self
+    }
+
+    /// The directives whose unknown level names were replaced, see [`with_lenient_parsing`].
+    ///
+    /// [`with_lenient_parsing`]: #method.with_lenient_parsing
+    pub fn parse_warnings(&self) -> &[String] {
+        &self.parse_warnings
+    }
+
+    /// Only log records of exactly `target`, up to `level`.
+    ///
+    /// This replaces the default level and module levels with a single target that is checked by
+    /// equality, which is cheaper than matching module prefixes when a canister only ever logs
+    /// under one target. Records of other targets, including sub-modules of `target`, are dropped.
+    /// Calling [`with_level`] or [`with_module_level`] afterwards goes back to regular filtering.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new().single_target("my_canister", LevelFilter::Info).init().unwrap();
+    /// ```
+    ///
+    /// [`with_level`]: #method.with_level
+    /// [`with_module_level`]: #method.with_module_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn single_target(mut self, target: &str, level: LevelFilter) -> IcLogger {
+        self.filter = Filter::single_target(target, level);
+        self
+    }
+
+    /// Only log records whose metadata satisfies `filter`, on top of all other filters.
+    ///
+    /// The predicate is consulted last, once the default and module levels, the passthrough and
+    /// all other built-in filters let a record through, so it can only restrict what they allow.
+    /// This makes it possible to implement arbitrary policies, e.g. based on the caller:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::{Level, LevelFilter};
+    ///
+    /// IcLogger::new()
+    ///     .with_level(LevelFilter::Debug)
+    ///     .with_filter_fn(|metadata| metadata.level() <= Level::Info || !metadata.target().ends_with("::api"))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_filter_fn(mut self, filter: impl Fn(&Metadata) -> bool + Send + Sync + 'static) -> IcLogger {
+        self.filter_fn = Some(Box::new(filter));
+        self
+    }
+
+    /// Always log records at or above the given level, whatever the module levels say.
+    ///
+    /// The passthrough takes precedence over the default and module levels, even over modules set
+    /// to [`LevelFilter::Off`], so that critical records can't get lost to a misconfigured filter.
+    /// It is disabled by default, which is the same as passing [`LevelFilter::Off`].
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new()
+    ///     .with_module_level("chatty_dependency", LevelFilter::Off)
+    ///     .with_passthrough(LevelFilter::Error)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_passthrough(mut self, level: LevelFilter) -> IcLogger {
+        self.passthrough = level;
+        self
+    }
+
+    /// Always log records of a specific module and its sub-modules, at any level.
+    ///
+    /// Matching records skip level filtering entirely, which suits channels like operational
+    /// alerts that must never be filtered by accident.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_always_log("alerts").init().unwrap();
+    /// log::debug!(target: "alerts", "cycles balance is low");
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_always_log(mut self, target_prefix: &str) -> IcLogger {
+        self.always_log.push(target_prefix.to_string());
+        self
+    }
+
+    /// Drop records of a specific module and its sub-modules that carry no key-values.
+    ///
+    /// This enforces structured logging in subsystems whose logs are processed by machines:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_require_kv("my_canister::audit").init().unwrap();
+    /// log::warn!(target: "my_canister::audit", user = "alice"; "login"); // logged
+    /// log::warn!(target: "my_canister::audit", "login"); // dropped
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_require_kv(mut self, target_prefix: &str) -> IcLogger {
+        self.require_kv.push(target_prefix.to_string());
+        self
+    }
+
+    /// Only log records of the given crate, silencing all dependencies.
+    ///
+    /// This is a shorthand for setting the default level to [`LevelFilter::Off`] and the level of
+    /// `crate_name` to `level`.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new().with_crate_only(env!("CARGO_CRATE_NAME"), LevelFilter::Debug).init().unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_crate_only(self, crate_name: &str, level: LevelFilter) -> IcLogger {
+        self.with_level(LevelFilter::Off).with_module_level(crate_name, level)
+    }
+
+    /// Only log records at or above `level` during a daily window.
+    ///
+    /// The window starts at `start` and ends at `end` nanoseconds since midnight UTC, as measured
+    /// by the clock, see [`with_clock`]. A window whose end is before its start spans midnight.
+    /// This keeps e.g. debug logging from adding to the load during predictably busy hours:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// const HOUR: u64 = 3_600_000_000_000;
+    ///
+    /// IcLogger::new()
+    ///     .with_level(LevelFilter::Debug)
+    ///     .with_quiet_window(12 * HOUR, 14 * HOUR, LevelFilter::Warn)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_clock`]: #method.with_clock
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_quiet_window(mut self, start: u64, end: u64, level: LevelFilter) -> IcLogger {
+        self.quiet_window = Some((start, end, level));
+        self
+    }
+
+    /// Tag every line with a build version.
+    ///
+    /// This makes it possible to match logs to the deployed wasm. The version is rendered
+    /// as the first column of the line prefix, e.g. `[0.1.0 WARN  my_canister] message`, or as
+    /// the `version` key of JSON lines.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_version(env!("CARGO_PKG_VERSION")).init().unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_version(mut self, version: &str) -> IcLogger {
+        self.format.version = Some(version.to_string());
+        self
+    }
+
+    /// Align the key-values of a line into columns.
+    ///
+    /// Key-values are rendered as `key=value` after the message. When enabled, all keys of a
+    /// line are padded to the width of its longest key, which makes dense structured logs easier
+    /// to scan.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_kv_align(true).init().unwrap();
+    /// log::warn!(id = 7, status = "failed"; "request done");
+    /// // [WARN  my_canister] request done id    =7 status=failed
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_kv_align(mut self, enabled: bool) -> IcLogger {
+        self.format.kv_align = enabled;
+        self
+    }
+
+    /// Prefix every line with the time at which it was logged.
+    ///
+    /// Timestamps are rendered in RFC 3339 format in UTC, e.g. `2023-07-27T23:08:09.718590904Z`.
+    ///
+    /// Without the `timestamps` feature this does nothing, lines never get a timestamp and the
+    /// time is not read for them.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_timestamps(mut self, enabled: bool) -> IcLogger {
+        self.timestamps = enabled;
+        self
+    }
+
+    /// Set how timestamps are rendered, see [`with_timestamps`].
+    ///
+    /// With [`TimestampFormat::SinceInit`], lines show the time elapsed since [`init`] instead of
+    /// the absolute time, e.g. `[+0.250s WARN  my_canister] message`, which makes test and
+    /// benchmark logs easier to read. Only [`Format::Human`] lines are affected. Without the
+    /// `timestamps` feature this does nothing.
+    ///
+    /// ```no_run
+    /// use ic_logger::{IcLogger, TimestampFormat};
+    ///
+    /// IcLogger::new()
+    ///     .with_timestamps(true)
+    ///     .with_timestamp_format(TimestampFormat::SinceInit)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_timestamps`]: #method.with_timestamps
+    /// [`init`]: #method.init
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_timestamp_format(mut self, format: TimestampFormat) -> IcLogger {
+        self.format.timestamp_format = format;
+        self
+    }
+
+    /// Prefix lines of a specific module and its sub-modules with a timestamp.
+    ///
+    /// This overrides [`with_timestamps`] for the matching targets, so that timestamps can be shown
+    /// only for the subsystems where they are useful. Without the `timestamps` feature this does
+    /// nothing.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_target_timestamp("my_canister::perf").init().unwrap();
+    /// ```
+    ///
+    /// [`with_timestamps`]: #method.with_timestamps
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_target_timestamp(mut self, prefix: &str) -> IcLogger {
+        self.timestamp_targets.push(prefix.to_string());
+        self
+    }
+
+    /// Set the source of timestamps.
+    ///
+    /// The clock returns nanoseconds since the unix epoch and defaults to [`ic_cdk::api::time`],
+    /// or the system time outside of a canister. Replacing it is mostly useful for testing.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_clock(mut self, clock: fn() -> u64) -> IcLogger {
+        self.clock = clock;
+        self
+    }
+
+    /// Set the source of the number of instructions the current message executed.
+    ///
+    /// The counter defaults to [`ic_cdk::api::performance_counter`], it's used by [`span`] and
+    /// [`with_instructions_remaining`]. Replacing it is mostly useful for testing outside of a
+    /// canister.
+    ///
+    /// [`span`]: crate::span
+    /// [`with_instructions_remaining`]: #method.with_instructions_remaining
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_instruction_counter(mut self, counter: fn() -> u64) -> IcLogger {
+        self.instruction_counter = counter;
+        self
+    }
+
+    /// Append a short hash of the message to every line.
+    ///
+    /// The hash is the 32-bit FNV-1a hash of the message rendered as 8 hex digits, e.g. `#a9055c11`.
+    /// It is stable across builds, so downstream systems can collapse identical messages without
+    /// comparing them in full. It is not a cryptographic hash.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_message_hash(mut self, enabled: bool) -> IcLogger {
+        self.format.message_hash = enabled;
+        self
+    }
+
+    /// Add a static tag to every line.
+    ///
+    /// This helps to tell apart the logs of different canisters or subsystems that end up in the
+    /// same place, e.g. `[payments WARN  my_canister] message`, or the `tag` key of JSON lines.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_tag(mut self, tag: &str) -> IcLogger {
+        self.format.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Add the source file and line of the record to every line.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_location(mut self, enabled: bool) -> IcLogger {
+        self.format.location = enabled;
+        self
+    }
+
+    /// Arrange the columns of a line.
+    ///
+    /// The columns before [`Column::Message`] form the bracketed prefix of a line and the ones after
+    /// it a bracketed suffix. Columns that are left out are not rendered at all.
+    ///
+    /// ```no_run
+    /// use ic_logger::{Column, IcLogger};
+    ///
+    /// IcLogger::new()
+    ///     .with_timestamps(true)
+    ///     .with_column_order(&[Column::Level, Column::Target, Column::Message, Column::Timestamp])
+    ///     .init()
+    ///     .unwrap();
+    /// // [WARN  my_canister] message [2023-07-27T23:08:09.718590904Z]
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `columns` doesn't contain [`Column::Message`].
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_column_order(mut self, columns: &[Column]) -> IcLogger {
+        assert!(
+            columns.contains(&Column::Message),
+            "The column order must contain Column::Message"
+        );
+        self.format.columns = columns.to_vec();
+        self
+    }
+
+    /// Apply a group of formatting options at once, see [`Preset`].
+    ///
+    /// A preset sets the format, the column order, timestamps and the source location, so it
+    /// overrides earlier calls to [`with_format`], [`with_column_order`], [`with_timestamps`] and
+    /// [`with_location`]. Later calls refine the preset.
+    ///
+    /// ```no_run
+    /// use ic_logger::{IcLogger, Preset};
+    ///
+    /// IcLogger::new().with_preset(Preset::Verbose).init().unwrap();
+    /// ```
+    ///
+    /// [`with_format`]: #method.with_format
+    /// [`with_column_order`]: #method.with_column_order
+    /// [`with_timestamps`]: #method.with_timestamps
+    /// [`with_location`]: #method.with_location
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_preset(self, preset: Preset) -> IcLogger {
+        let (format, columns, timestamps, location) = match preset {
+            Preset::Compact => (
+                Format::Human,
+                &[Column::Level, Column::Target, Column::Message][..],
+                false,
+                false,
+            ),
+            Preset::Verbose => (Format::Human, &DEFAULT_COLUMNS[..], true, true),
+            Preset::Json => (Format::Json, &DEFAULT_COLUMNS[..], true, false),
+        };
+        self.with_format(format)
+            .with_column_order(columns)
+            .with_timestamps(timestamps)
+            .with_location(location)
+    }
+
+    /// Set the overall shape of lines, see [`Format`].
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_format(mut self, format: Format) -> IcLogger {
+        self.format.kind = format;
+        self
+    }
+
+    /// Add tree-style guides to multiline messages delimited by braces, like whole structs logged
+    /// with `{:#?}`:
+    ///
+    /// ```text
+    /// [DEBUG my_canister] State {
+    ///   │     users: 3,
+    ///   └ }
+    /// ```
+    ///
+    /// Other messages are rendered as they are. This only applies to [`Format::Human`] lines.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_pretty_debug(mut self) -> IcLogger {
+        self.format.pretty_debug = true;
+        self
+    }
+
+    /// Trim trailing whitespace, like spaces and line breaks, from messages before rendering them.
+    ///
+    /// Messages built from external input often end in a stray line break, which shows up as an
+    /// empty line in the output. Leading whitespace is kept, it may be intended for alignment.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_trim(mut self, enabled: bool) -> IcLogger {
+        self.format.trim = enabled;
+        self
+    }
+
+    /// Only render the last segment of targets, e.g. `db` instead of `my_canister::storage::db`.
+    ///
+    /// This only affects how lines look, records are still filtered by their full target.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_short_target(mut self, enabled: bool) -> IcLogger {
+        self.format.short_target = enabled;
+        self
+    }
+
+    /// Render levels as their syslog severity, e.g. `[4     my_canister] message` for a warning.
+    ///
+    /// The severities follow RFC 5424, see [`syslog_severity`], which lets syslog-style collectors
+    /// ingest the lines as they are. [`Format::Json`] lines get the severity as a number, e.g.
+    /// `"level":4`.
+    ///
+    /// [`syslog_severity`]: crate::format::syslog_severity
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_syslog_severity(mut self) -> IcLogger {
+        self.format.syslog_severity = true;
+        self
+    }
+
+    /// Include the number of key-values of a record in [`Format::Json`] lines, e.g. `"field_count":2`.
+    ///
+    /// The count is rendered for every line, so consumers can tell whether a line carries
+    /// structured data without looking for the `fields` object.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_json_field_count(mut self, enabled: bool) -> IcLogger {
+        self.format.json_field_count = enabled;
+        self
+    }
+
+    /// Set the casing of the level of [`Format::Json`] lines, e.g. `"level":"warn"`.
+    ///
+    /// Defaults to [`Case::Upper`], matching the other formats.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_json_level_case(mut self, case: Case) -> IcLogger {
+        self.format.json_level_case = case;
+        self
+    }
+
+    /// Set the version rendered as the `v` key of [`Format::Json`] lines.
+    ///
+    /// It defaults to [`JSON_SCHEMA_VERSION`], the version of the layout of this crate. A version
+    /// of your own lets consumers tell apart the lines of different releases of a canister, e.g.
+    /// when the key-values it logs change.
+    ///
+    /// [`JSON_SCHEMA_VERSION`]: crate::JSON_SCHEMA_VERSION
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_json_schema_version(mut self, version: u32) -> IcLogger {
+        self.format.json_schema_version = version;
+        self
+    }
+
+    /// Print the header of [`Format::Csv`] lines, `level,target,message`, once in [`init`].
+    ///
+    /// The header is not a record, it's printed with the [`Backend`] only. Sinks, forwarders and
+    /// the buffer don't receive it, and it doesn't count towards the output budget.
+    ///
+    /// [`init`]: #method.init
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_csv_header(mut self, enabled: bool) -> IcLogger {
+        self.csv_header = enabled;
+        self
+    }
+
+    /// Arrange the timestamp, level and target columns in one of the common orders.
+    ///
+    /// This is a shorthand for [`with_column_order`] with the columns of [`FieldOrder::columns`].
+    ///
+    /// [`with_column_order`]: #method.with_column_order
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_field_order(self, order: FieldOrder) -> IcLogger {
+        self.with_column_order(&order.columns())
+    }
+
+    /// Add the current size of stable memory, in bytes, to every line as a `stable_size` field.
+    ///
+    /// This helps to track the growth of stable memory, e.g. during migrations:
+    /// `[WARN  my_canister] migrated users stable_size=131072`.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_stable_size(mut self) -> IcLogger {
+        self.stable_size = Some(stable_size);
+        self
+    }
+
+    /// Add the number of instructions left before the message hits its limit as a `rem` field.
+    ///
+    /// The count is [`INSTRUCTION_LIMIT`] minus the instructions the message executed so far, which
+    /// shows how close a long running update gets to being trapped: `[WARN  my_canister] batch
+    /// done rem=1250000000`.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_instructions_remaining(mut self) -> IcLogger {
+        self.instructions_remaining = Some(INSTRUCTION_LIMIT);
+        self
+    }
+
+    /// Tag every line with the id of the session, added as a `session` field.
+    ///
+    /// The id is the time at which the logger is installed, so it's different for every instance
+    /// of a canister, e.g. across upgrades: `[WARN  my_canister] migrated session=1690499289718590904`.
+    /// Entries restored from a previous session with [`restore_logs`] keep their original id,
+    /// which tells them apart from the ones of the current session.
+    ///
+    /// [`restore_logs`]: crate::restore_logs
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_session_id(mut self) -> IcLogger {
+        self.session = Some(0);
+        self
+    }
+
+    /// Tag all lines of a call with a generated id, added as a `cid` field.
+    ///
+    /// A call is detected by the time of the clock changing, which is the same for all records of
+    /// a message, so the lines of a single request can be picked out of interleaved output:
+    /// `[WARN  my_canister] low balance cid=5f3a9c01`. Messages executed in the same round share
+    /// the time, so call [`reset_correlation_id`] at the start of a method to tell them apart.
+    ///
+    /// [`reset_correlation_id`]: crate::reset_correlation_id
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_auto_correlation(mut self, enabled: bool) -> IcLogger {
+        self.correlation = enabled.then(Correlation::default);
+        self
+    }
+
+    /// Start exports of the buffer with a JSON header describing them.
+    ///
+    /// [`get_logs_text`] and the first chunk of [`export_chunks`] begin with a single line like
+    /// `{"v":2,"kind":"header","canister":"rrkah-fqaaa-aaaaa-aaaaq-cai","session":1690499289718590904}`,
+    /// with the schema version of [`Format::Json`] lines, the id of the canister and the session
+    /// id, if enabled with [`with_session_id`]. The buffered lines themselves are not affected.
+    ///
+    /// [`get_logs_text`]: crate::get_logs_text
+    /// [`export_chunks`]: crate::export_chunks
+    /// [`with_session_id`]: #method.with_session_id
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_export_header(mut self) -> IcLogger {
+        self.export_header = true;
+        self
+    }
+
+    /// Replace invalid UTF-8 in messages logged with [`log_bytes`] by `U+FFFD` replacement characters.
+    ///
+    /// Messages produced by binary formatters may not be valid text. By default the invalid bytes
+    /// are escaped, e.g. `\xff`, which keeps them recoverable, this renders them like
+    /// [`String::from_utf8_lossy`] instead, which suits sinks that expect readable text.
+    ///
+    /// [`log_bytes`]: crate::log_bytes
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_lossy_utf8(mut self) -> IcLogger {
+        self.lossy_utf8 = true;
+        self
+    }
+
+    /// Number every line with a logical clock, added as a `seq` field.
+    ///
+    /// The time of the IC is the same for all records of a message, so timestamps alone can't tell
+    /// their order apart: `[WARN  my_canister] first seq=7`. By default the number keeps growing
+    /// for the lifetime of the logger, see [`with_logical_clock_reset`] to restart it instead.
+    ///
+    /// [`with_logical_clock_reset`]: #method.with_logical_clock_reset
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_logical_clock(mut self, enabled: bool) -> IcLogger {
+        let reset = self
+            .sequence
+            .as_ref()
+            .map_or_else(SequenceReset::default, Sequence::reset);
+        self.sequence = enabled.then(|| Sequence::new(reset));
+        self
+    }
+
+    /// Set when the logical clock of [`with_logical_clock`] starts counting from zero again.
+    ///
+    /// This enables the logical clock.
+    ///
+    /// [`with_logical_clock`]: #method.with_logical_clock
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_logical_clock_reset(mut self, reset: SequenceReset) -> IcLogger {
+        self.sequence = Some(Sequence::new(reset));
+        self
+    }
+
+    /// Set where lines are written, see [`Backend`].
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_backend(mut self, backend: Backend) -> IcLogger {
+        self.backend = backend;
+        self
+    }
+
+    /// Drop all records logged during query calls.
+    ///
+    /// Query calls can be very frequent and their state changes are discarded anyway. Calls that
+    /// are not queries, including `init`, timers and heartbeats, still log as usual.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_update_only(mut self, enabled: bool) -> IcLogger {
+        self.update_only = enabled;
+        self
+    }
+
+    /// Drop all records while the cycle balance of the canister is below `min_remaining`.
+    ///
+    /// Logging costs cycles, this keeps a canister that is about to run out of them from spending
+    /// the last ones on its logs. Outside of a canister there is no balance to check, so nothing
+    /// is dropped.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_cycles_gate(mut self, min_remaining: u128) -> IcLogger {
+        self.cycles_gate = Some(min_remaining);
+        self
+    }
+
+    /// Keep the most recent `capacity` lines in memory.
+    ///
+    /// The buffered lines can be retrieved with [`get_logs`] and [`export_chunks`], e.g. to expose
+    /// them through a query method. Lines are still printed as usual.
+    ///
+    /// [`get_logs`]: crate::get_logs
+    /// [`export_chunks`]: crate::export_chunks
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_buffer(mut self, capacity: usize) -> IcLogger {
+        self.buffer = Some(Mutex::new(Buffer::new(capacity, None)));
+        self
+    }
+
+    /// Keep the most recent lines in memory, as many as fit into `max_bytes`.
+    ///
+    /// Unlike [`with_buffer`], which keeps a number of lines whatever their length, this caps the
+    /// memory taken by the buffered lines, which is more predictable for canisters that are short
+    /// on memory. The oldest lines are evicted until a new one fits, a line longer than the whole
+    /// cap is handled according to [`with_oversized_line_policy`].
+    ///
+    /// [`with_buffer`]: #method.with_buffer
+    /// [`with_oversized_line_policy`]: #method.with_oversized_line_policy
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_ring_buffer_bytes(mut self, max_bytes: usize) -> IcLogger {
+        self.buffer = Some(Mutex::new(Buffer::new(usize::MAX, Some(max_bytes))));
+        self
+    }
+
+    /// Keep the most recent `capacity` lines of the targets starting with `target_prefix` in a
+    /// buffer of their own.
+    ///
+    /// This keeps the lines of a busy subsystem from evicting the ones of all others. The lines
+    /// are retrieved with [`get_named_logs`] and never end up in the default buffer of
+    /// [`with_buffer`]. When several prefixes match a target, the one added first wins:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new()
+    ///     .with_buffer(100)
+    ///     .with_named_buffer("payments", 1000)
+    ///     .init()
+    ///     .unwrap();
+    ///
+    /// let payments = ic_logger::get_named_logs("payments");
+    /// ```
+    ///
+    /// [`get_named_logs`]: crate::get_named_logs
+    /// [`with_buffer`]: #method.with_buffer
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_named_buffer(mut self, target_prefix: &str, capacity: usize) -> IcLogger {
+        let buffer = Mutex::new(Buffer::new(capacity, None));
+        self.named_buffers.push((target_prefix.to_string(), buffer));
+        self
+    }
+
+    /// Set what the buffer does with a line longer than its whole byte cap, see [`with_ring_buffer_bytes`].
+    ///
+    /// Defaults to [`OversizedLinePolicy::DropAndCount`], the dropped lines are counted by
+    /// [`oversized_count`](crate::oversized_count).
+    ///
+    /// [`with_ring_buffer_bytes`]: #method.with_ring_buffer_bytes
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_oversized_line_policy(mut self, policy: OversizedLinePolicy) -> IcLogger {
+        self.oversized_line_policy = policy;
+        self
+    }
+
+    /// Print records up to the `console` level and keep records up to the `buffer` level in memory.
+    ///
+    /// This wires the common "everything into the buffer, only warnings to the console" setup in
+    /// one call. The default level is set to the more verbose of the two and buffering is enabled
+    /// with a capacity of [`DEFAULT_BUFFER_CAPACITY`] lines, unless [`with_buffer`] set another one.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// IcLogger::new()
+    ///     .with_console_and_buffer(LevelFilter::Warn, LevelFilter::Debug)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_buffer`]: #method.with_buffer
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_console_and_buffer(mut self, console: LevelFilter, buffer: LevelFilter) -> IcLogger {
+        self.console_level = console;
+        self.buffer_level = buffer;
+        if self.buffer.is_none() {
+            self = self.with_buffer(DEFAULT_BUFFER_CAPACITY);
+        }
+        self.with_level(console.max(buffer))
+    }
+
+    /// Run `interceptor` on every fully formatted line before it is written anywhere.
+    ///
+    /// The interceptor returns the line to write, which may be rewritten, or `None` to drop it.
+    /// Unlike filtering by level or target, it sees the final line, including all its columns:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new()
+    ///     .with_line_interceptor(|line| match line.contains("heartbeat") {
+    ///         true => None,
+    ///         false => Some(line.replace("secret", "******")),
+    ///     })
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Lines rendered in a format of their own for a sink, see [`add_sink_with_format`], are
+    /// intercepted too.
+    ///
+    /// [`add_sink_with_format`]: #method.add_sink_with_format
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_line_interceptor(
+        mut self,
+        interceptor: impl Fn(String) -> Option<String> + Send + Sync + 'static,
+    ) -> IcLogger {
+        self.interceptor = Some(Box::new(interceptor));
+        self
+    }
+
+    /// Also write every line to `sink`, in the format of the logger.
+    ///
+    /// Sinks receive lines next to the [`Backend`], they are not affected by [`capture`] or
+    /// batching. Use [`add_sink_with_format`] to give a sink a format of its own.
+    ///
+    /// [`add_sink_with_format`]: #method.add_sink_with_format
+    #[must_use = "You must call init() to begin logging"]
+    pub fn add_sink(mut self, sink: impl Sink + 'static) -> IcLogger {
+        self.sinks.push((Box::new(sink), None));
+        self
+    }
+
+    /// Also write every line to `sink`, rendered in `format` regardless of [`with_format`].
+    ///
+    /// This allows e.g. a human readable console next to a JSON sink. Every record is rendered
+    /// once per distinct format, no matter how many sinks share it.
+    ///
+    /// ```no_run
+    /// use ic_logger::{Format, IcLogger};
+    ///
+    /// IcLogger::new()
+    ///     .add_sink_with_format(|_level, _target: &str, line: &str| ic_cdk::println!("{line}"), Format::Json)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_format`]: #method.with_format
+    #[must_use = "You must call init() to begin logging"]
+    pub fn add_sink_with_format(mut self, sink: impl Sink + 'static, format: Format) -> IcLogger {
+        self.sinks.push((Box::new(sink), Some(format)));
+        self
+    }
+
+    /// Write the records of a module and its sub-modules only to `sink`.
+    ///
+    /// Matching records skip all other outputs: the [`Backend`], the other sinks, the buffer and
+    /// the forwarder. This separates records that aren't meant for people, e.g. sending the
+    /// `metrics` target to a sink that updates counters:
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new()
+    ///     .with_target_sink("my_canister::metrics", |_level, _target: &str, line: &str| {
+    ///         // update the counters
+    ///     })
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Use [`with_target_sink_and_default`] to write matching records to the other outputs too.
+    ///
+    /// [`with_target_sink_and_default`]: #method.with_target_sink_and_default
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_target_sink(mut self, target: &str, sink: impl Sink + 'static) -> IcLogger {
+        self.target_sinks.push((target.to_string(), Box::new(sink), false));
+        self
+    }
+
+    /// Write the records of a module and its sub-modules to `sink`, next to all other outputs.
+    ///
+    /// Unlike [`with_target_sink`], matching records are still written as usual.
+    ///
+    /// [`with_target_sink`]: #method.with_target_sink
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_target_sink_and_default(mut self, target: &str, sink: impl Sink + 'static) -> IcLogger {
+        self.target_sinks.push((target.to_string(), Box::new(sink), true));
+        self
+    }
+
+    /// Add a sink, see [`add_sink`], that only exists in builds with `debug_assertions`.
+    ///
+    /// In release builds the sink is dropped right away, so verbose diagnostics vanish without any
+    /// check at runtime.
+    ///
+    /// [`add_sink`]: #method.add_sink
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_debug_sink(self, sink: impl Sink + 'static) -> IcLogger {
+        #[cfg(debug_assertions)]
+        return self.add_sink(sink);
+
+        #[cfg(not(debug_assertions))]
+        {
+            drop(sink);
+            self
+        }
+    }
+
+    /// Write errors and warnings to the error stream and everything else to the regular one.
+    ///
+    /// Sinks receive their lines through [`Sink::write_stream`], so that ones with a separate
+    /// error output can route them there. The canister output of the CDK has a single stream, so
+    /// the lines printed by the logger itself, like the ones of sinks without an error output, are
+    /// prefixed with [`STDERR_MARKER`] instead: `[stderr] [ERROR my_canister] message`.
+    ///
+    /// [`STDERR_MARKER`]: crate::STDERR_MARKER
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_split_streams(mut self) -> IcLogger {
+        self.split_streams = true;
+        self
+    }
+
+    /// Hold lines back and print them all at once when the logger is flushed.
+    ///
+    /// Printing every line on its own has a per-call cost. With batching, lines accumulate until
+    /// [`flush_on_heartbeat`] (or [`log::logger().flush()`](Log::flush)) prints them in a single
+    /// call, typically once per heartbeat:
+    ///
+    /// ```no_run
+    /// #[ic_cdk::heartbeat]
+    /// fn heartbeat() {
+    ///     ic_logger::flush_on_heartbeat();
+    /// }
+    /// ```
+    ///
+    /// The tradeoff is delayed visibility: lines only show up after the next flush, and the ones
+    /// held back by a message that traps are lost together with the rest of its state changes.
+    ///
+    /// [`flush_on_heartbeat`]: crate::flush_on_heartbeat
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_batching(mut self, enabled: bool) -> IcLogger {
+        self.pending = enabled.then(|| Mutex::new(Vec::new()));
+        self
+    }
+
+    /// Flush the batched lines right away when a record at or above `level` is logged.
+    ///
+    /// With [`with_batching`], errors would otherwise only show up after the next flush and get
+    /// lost if the message traps. Records at or above `level` are printed immediately, after the
+    /// lines held back so far to keep their order, while less severe ones keep accumulating.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::Level;
+    ///
+    /// IcLogger::new()
+    ///     .with_batching(true)
+    ///     .with_batch_flush_level(Level::Error)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_batching`]: #method.with_batching
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_batch_flush_level(mut self, level: Level) -> IcLogger {
+        self.batch_flush_level = Some(level);
+        self
+    }
+
+    /// Ship every logged entry to a collector canister.
+    ///
+    /// Each entry is sent as a Candid-encoded [`LogEntry`] argument of a one-way call to `method`
+    /// of `collector`, so the collector should expose an update method like:
+    ///
+    /// ```no_run
+    /// #[ic_cdk::update]
+    /// fn ingest(entry: ic_logger::LogEntry) {
+    ///     // store the entry
+    /// }
+    /// ```
+    ///
+    /// Every forward is an inter-canister call, which costs cycles, so this is best combined with a
+    /// restrictive level.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_forwarder(mut self, collector: Principal, method: &str) -> IcLogger {
+        self.forwarder = Some(Forwarder::new(collector, method));
+        self
+    }
+
+    /// Forward identical entries at most once per `interval`.
+    ///
+    /// Entries are identical when their level, target and message match. This keeps a repeated
+    /// error from flooding the collector set with [`with_forwarder`] and burning cycles on it.
+    /// The interval is measured with the clock, see [`with_clock`].
+    ///
+    /// [`with_forwarder`]: #method.with_forwarder
+    /// [`with_clock`]: #method.with_clock
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_forward_throttle(mut self, interval: Duration) -> IcLogger {
+        self.forward_throttle = Some(Throttle::new(interval.as_nanos().try_into().unwrap_or(u64::MAX)));
+        self
+    }
+
+    /// Call `callback` whenever the logger fails to deliver a line, like a rejected forward.
+    ///
+    /// The callback receives a description of the failure. Several callbacks can be registered,
+    /// they are called in the order of registration. A callback must not log through the
+    /// installed logger, which would recurse into the failing path.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new()
+    ///     .on_error(|error| ic_cdk::println!("logging failed: {error}"))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn on_error(mut self, callback: impl Fn(&str) + Send + Sync + 'static) -> IcLogger {
+        self.error_callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// The number of callbacks registered with [`on_error`].
+    ///
+    /// [`on_error`]: #method.on_error
+    pub fn error_callback_count(&self) -> usize {
+        self.error_callbacks.len()
+    }
+
+    /// Remove all callbacks registered with [`on_error`].
+    ///
+    /// This lets a reused configuration be set up again without the callbacks piling up.
+    ///
+    /// [`on_error`]: #method.on_error
+    pub fn clear_error_callbacks(&mut self) {
+        self.error_callbacks.clear();
+    }
+
+    /// Write at most `max` records per `interval`, dropping the rest.
+    ///
+    /// This caps the cost of logging when something goes wrong in a loop. Intervals are fixed
+    /// windows measured with the clock, see [`with_clock`], and the number of records dropped by
+    /// the limit is reported by [`rate_limited_count`]. Dropped records are also summarized as
+    /// `3 records dropped by the rate limit` before the next record that passes, or on flush.
+    ///
+    /// [`with_clock`]: #method.with_clock
+    /// [`rate_limited_count`]: #method.rate_limited_count
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_rate_limit(mut self, max: u64, interval: Duration) -> IcLogger {
+        let interval = interval.as_nanos().try_into().unwrap_or(u64::MAX);
+        self.rate_limit = Some(RateLimiter::new(max, interval));
+        self
+    }
+
+    /// Write the first `first` records of the targets starting with `target`, then only every
+    /// `then_every`th one.
+    ///
+    /// This suits long running loops: the first iterations show up in full, the later ones are
+    /// thinned out. A `then_every` of 0 drops all records after the first ones. The count only
+    /// includes records that passed the filters and never restarts.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    ///
+    /// IcLogger::new().with_progressive_sampling("my_canister::sync", 10, 100).init().unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_progressive_sampling(mut self, target: &str, first: u32, then_every: u32) -> IcLogger {
+        let sampler = Sampler::new(first.into(), then_every.into());
+        self.samplers.push((target.to_string(), sampler));
+        self
+    }
+
+    /// Split messages longer than `max_bytes` into several lines tagged with their part.
+    ///
+    /// Downstream limits may drop or cut a single huge line, this keeps every part of the message
+    /// within the budget instead, e.g. with `with_chunking(8)`:
+    ///
+    /// ```text
+    /// [WARN  my_canister] a long m (part 1/2)
+    /// [WARN  my_canister] essage (part 2/2)
+    /// ```
+    ///
+    /// Only the message counts against `max_bytes`, the columns around it and the tag are added on
+    /// top. Messages are split between characters, a part holds at least one of them.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_chunking(mut self, max_bytes: usize) -> IcLogger {
+        self.chunk_size = Some(max_bytes);
+        self
+    }
+
+    /// Stop logging once the written lines add up to `max_bytes`.
+    ///
+    /// This is a hard backstop against the cost of runaway logging. The line that crosses the
+    /// budget is still written, the first record after it is replaced by a single warning that the
+    /// budget is exhausted and all later records are dropped. Only the lines of the configured
+    /// format count against the budget, lines rendered for sinks in a format of their own don't.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_output_budget(mut self, max_bytes: u64) -> IcLogger {
+        self.output_budget = Some(OutputBudget::new(max_bytes));
+        self
+    }
+
+    /// The number of records dropped by the rate limit, see [`with_rate_limit`].
+    ///
+    /// Records that are filtered out by their level are not counted.
+    ///
+    /// [`with_rate_limit`]: #method.with_rate_limit
+    pub fn rate_limited_count(&self) -> u64 {
+        self.rate_limit.as_ref().map_or(0, RateLimiter::dropped)
+    }
+
+    /// The number of records that were logged but filtered out, e.g. by their level.
+    ///
+    /// Records below the max level of the `log` macros never reach the logger and are not counted,
+    /// neither are records dropped by [`with_rate_limit`] or [`with_dedup`]. Comparing the count
+    /// against the number of written lines helps to tune the filters.
+    ///
+    /// [`with_rate_limit`]: #method.with_rate_limit
+    /// [`with_dedup`]: #method.with_dedup
+    pub fn suppressed_count(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    /// The targets of the records that were logged so far, whether they passed the filters or not.
+    ///
+    /// This shows which targets a canister actually logs under, e.g. to write the directives of
+    /// [`with_directives`]. Records below the max level of the `log` macros never reach the logger
+    /// and their targets are not seen. At most [`SEEN_TARGETS_LIMIT`] targets are remembered, later
+    /// new ones are ignored.
+    ///
+    /// [`with_directives`]: #method.with_directives
+    pub fn seen_targets(&self) -> Vec<String> {
+        let seen = self.seen_targets.lock().unwrap_or_else(PoisonError::into_inner);
+        seen.iter().cloned().collect()
+    }
+
+    /// Remember the target of a record, see [`seen_targets`](#method.seen_targets).
+    fn see_target(&self, target: &str) {
+        let mut seen = self.seen_targets.lock().unwrap_or_else(PoisonError::into_inner);
+        if seen.len() < SEEN_TARGETS_LIMIT && !seen.contains(target) {
+            seen.insert(target.to_string());
+        }
+    }
+
+    /// Collapse consecutive identical records.
+    ///
+    /// Records are identical when their level, target and message match. Repeats are dropped and
+    /// summarized as `last message repeated 5 times` once a different record is logged or the
+    /// logger is flushed.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_dedup(mut self, enabled: bool) -> IcLogger {
+        self.dedup = enabled.then(Dedup::new);
+        self
+    }
+
+    /// Set the level of summaries of dropped records, like `last message repeated 5 times`.
+    ///
+    /// This covers the summaries of [`with_dedup`], which by default have the level of the record
+    /// they summarize, and the ones of [`with_rate_limit`], which are [`Level::Warn`] by default.
+    /// A fixed level, like [`Level::Info`], keeps summaries consistent regardless of the original
+    /// records. The notice of [`with_output_budget`] is always [`Level::Warn`].
+    ///
+    /// [`with_dedup`]: #method.with_dedup
+    /// [`with_rate_limit`]: #method.with_rate_limit
+    /// [`with_output_budget`]: #method.with_output_budget
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_summary_level(mut self, level: Level) -> IcLogger {
+        self.summary_level = Some(level);
+        self
+    }
+
+    /// The level rules of the logger, in the order they are matched against a target.
+    ///
+    /// The rules set with [`with_module_level`] come first, from the most to the least specific
+    /// one, followed by the level set with [`with_level`] for the empty prefix. This makes it easy
+    /// to check the configuration that is actually in effect.
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    /// [`with_level`]: #method.with_level
+    pub fn resolved_rules(&self) -> Vec<(String, LevelFilter)> {
+        self.filter.rules()
+    }
+
+    /// A human-readable, multi-line summary of the configuration that is in effect.
+    ///
+    /// It lists the default level, the [`resolved_rules`] in the order they are matched and the
+    /// options that are enabled, which helps to find out why logging doesn't behave as expected:
+    ///
+    /// ```text
+    /// default level: WARN
+    /// rules, from the first to the last match:
+    ///   my_crate::db => TRACE
+    ///   <default> => WARN
+    /// format: Human
+    /// backend: Println
+    /// buffer: 100 lines
+    /// ```
+    ///
+    /// [`resolved_rules`]: #method.resolved_rules
+    pub fn describe(&self) -> String {
+        let mut lines = vec![
+            format!("default level: {}", self.filter.default_level()),
+            "rules, from the first to the last match:".to_string(),
+        ];
+        for (target, level) in self.resolved_rules() {
+            let target = if target.is_empty() { "<default>" } else { &target };
+            lines.push(format!("  {target} => {level}"));
+        }
+        if self.passthrough != LevelFilter::Off {
+            lines.push(format!("passthrough: {}", self.passthrough));
+        }
+        if self.floor != LevelFilter::Off {
+            lines.push(format!("floor: {}", self.floor));
+        }
+        if !self.always_log.is_empty() {
+            lines.push(format!("always logged: {}", self.always_log.join(", ")));
+        }
+        if !self.require_kv.is_empty() {
+            lines.push(format!("key-values required: {}", self.require_kv.join(", ")));
+        }
+        if self.filter_fn.is_some() {
+            lines.push("filter fn: set".to_string());
+        }
+        if self.update_only {
+            lines.push("update calls only: on".to_string());
+        }
+        if let Some(min_remaining) = self.cycles_gate {
+            lines.push(format!("cycles gate: {min_remaining}"));
+        }
+        if let Some((start, end, level)) = self.quiet_window {
+            lines.push(format!("quiet window: {start}..{end} at {level}"));
+        }
+        if self.rate_limit.is_some() {
+            lines.push("rate limit: on".to_string());
+        }
+        if self.dedup.is_some() {
+            lines.push("dedup: on".to_string());
+        }
+        lines.push(format!("format: {:?}", self.format.kind));
+        if self.timestamps {
+            lines.push("timestamps: on".to_string());
+        } else if !self.timestamp_targets.is_empty() {
+            lines.push(format!("timestamps: {}", self.timestamp_targets.join(", ")));
+        }
+        lines.push(format!("backend: {:?}", self.backend));
+        if self.console_level != LevelFilter::Trace {
+            lines.push(format!("console level: {}", self.console_level));
+        }
+        if let Some(buffer) = self.buffer() {
+            match buffer.max_bytes() {
+                Some(max_bytes) => lines.push(format!("buffer: {max_bytes} bytes")),
+                None => lines.push(format!("buffer: {} lines", buffer.capacity())),
+            }
+        }
+        if self.buffer_level != LevelFilter::Trace {
+            lines.push(format!("buffer level: {}", self.buffer_level));
+        }
+        if self.pending.is_some() {
+            lines.push("batching: on".to_string());
+        }
+        if !self.sinks.is_empty() {
+            lines.push(format!("sinks: {}", self.sinks.len()));
+        }
+        if self.forwarder.is_some() {
+            lines.push("forwarding: on".to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Combine the levels of `other` into this configuration, for layered configurations like
+    /// framework defaults with application overrides.
+    ///
+    /// The more verbose of the two default levels is kept. Module levels of `other` win over the
+    /// ones set for the same target here, module levels for other targets are kept from both. All
+    /// other options are taken from `self`.
+    ///
+    /// ```no_run
+    /// use ic_logger::IcLogger;
+    /// use log::LevelFilter;
+    ///
+    /// let defaults = IcLogger::new().with_module_level("framework", LevelFilter::Info);
+    /// let overrides = IcLogger::new().with_module_level("framework", LevelFilter::Error);
+    /// defaults.merge(overrides).init().unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn merge(mut self, other: IcLogger) -> IcLogger {
+        self.filter.merge(&other.filter);
+        self
+    }
+
+    /// Take a snapshot of the default level and module levels, see [`restore`].
+    ///
+    /// [`restore`]: #method.restore
+    pub fn snapshot(&self) -> ConfigSnapshot {
+        self.filter.snapshot()
+    }
+
+    /// Reapply the default level and module levels of a [`snapshot`].
+    ///
+    /// [`snapshot`]: #method.snapshot
+    pub fn restore(&mut self, snapshot: ConfigSnapshot) {
+        self.filter.restore(snapshot);
+    }
+
+    /// Register a target for checks that compare levels only, see [`enabled_id`].
+    ///
+    /// This is meant for hot loops, where matching the target against every module override on
+    /// each check adds up. Interning the same target again returns the same id. The id is only
+    /// valid for this logger.
+    ///
+    /// [`enabled_id`]: #method.enabled_id
+    pub fn intern_target(&mut self, target: &str) -> TargetId {
+        self.filter.intern_target(target)
+    }
+
+    /// Check whether a record of an interned target at `level` would be logged.
+    ///
+    /// This gives the same result as [`Log::enabled`] for the target, see [`intern_target`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was interned by a different logger.
+    ///
+    /// [`intern_target`]: #method.intern_target
+    pub fn enabled_id(&self, id: TargetId, level: Level) -> bool {
+        /* Looking the target up first rejects ids of other loggers, whichever check decides */
+        let target = self.filter.interned_target(id);
+        self.allows(level)
+            && (level <= self.unfiltered_level()
+                || (!self.always_log.is_empty() && self.always_logged(target))
+                || match self.level_fns.is_empty() {
+                    true => self.filter.enabled_id(id, level),
+                    false => self.target_enabled(target, level, || self.filter.enabled_id(id, level)),
+                })
+            && self
+                .filter_fn
+                .as_ref()
+                .is_none_or(|filter| filter(&Metadata::builder().level(level).target(target).build()))
+    }
+
+    /// Check whether a record with the given metadata would be kept in the buffer.
+    ///
+    /// This mirrors [`Log::enabled`] for the buffer: the record has to pass the filters, buffering
+    /// has to be enabled and the level has to be within the buffer threshold, see
+    /// [`with_console_and_buffer`]. It lets callers skip building expensive fields that are only
+    /// meant for the buffer.
+    ///
+    /// [`with_console_and_buffer`]: #method.with_console_and_buffer
+    pub fn would_buffer(&self, metadata: &Metadata) -> bool {
+        self.buffer_for(metadata.target()).is_some() && metadata.level() <= self.buffer_level && self.enabled(metadata)
+    }
+
+    /// Render the line that logging `record` would write, without writing it anywhere.
+    ///
+    /// Returns `None` if the record is filtered out. Filters that depend on the previously logged
+    /// records, like [`with_rate_limit`] and [`with_dedup`], are not taken into account. This makes
+    /// it possible to test a format configuration in isolation, e.g. against golden files.
+    ///
+    /// [`with_rate_limit`]: #method.with_rate_limit
+    /// [`with_dedup`]: #method.with_dedup
+    pub fn render(&self, record: &Record) -> Option<String> {
+        if !self.passes(record) {
+            return None;
+        }
+        let render = |record: &Record| {
+            self.with_fields(record, false, |record| {
+                self.intercept(self.format.format(record, self.timestamp(record)))
+            })
+        };
+        match record.target().strip_prefix(FORCE_PREFIX) {
+            Some(target) => render(&record.to_builder().target(target).build()),
+            None => render(record),
+        }
+    }
+
+    /// 'Init' the actual logger, instantiate it and configure it,
+    /// this method MUST be called in order for the logger to be effective.
+    ///
+    /// With the `production` feature, enabling [`Level::Trace`] for any module writes a one-time
+    /// warning, as formatting and printing trace records is costly on-chain.
+    ///
+    /// Fails with [`Error::AlreadyInitialized`] if a logger was already installed, with
+    /// [`Error::LoggerAlreadySet`] if a different logger was registered with the `log` crate, or
+    /// with [`Error::InvalidLevel`] if a directive has an unknown level name, see
+    /// [`with_directives`], or with [`Error::TooManyRules`] if more rules were added than allowed,
+    /// see [`with_max_rules`].
+    ///
+    /// [`with_directives`]: #method.with_directives
+    /// [`with_max_rules`]: #method.with_max_rules
+    pub fn init(mut self) -> Result<(), Error> {
+        self.validate()?;
+        let max_level = self.max_level();
+        self.start();
+
+        LOGGER.with(|installed| {
+            let mut installed = installed.borrow_mut();
+            if installed.is_some() {
+                return Err(Error::AlreadyInitialized);
+            }
+            /* `SetLoggerError` can't be constructed outside of `log`, but every registration after
+             * the first one fails, so this is how a different logger is reported.
+             */
+            if !*REGISTERED.get_or_init(|| log::set_logger(&INSTALLED_LOGGER).is_ok()) {
+                return Err(log::set_logger(&INSTALLED_LOGGER).unwrap_err().into());
+            }
+            log::set_max_level(max_level);
+            self.announce();
+            *installed = Some(self);
+            INSTALLED.set(true);
+            Ok(())
+        })
+    }
+}
+
+impl IcLogger {
+    /// Write what a logger writes once it's installed, like the CSV header and configuration warnings.
+    fn announce(&self) {
+        if self.csv_header && self.format.kind == Format::Csv {
+            self.backend.print(CSV_HEADER, self.print);
+        }
+        if cfg!(feature = "production")
+            && self.filter.max_level().max(self.passthrough) == LevelFilter::Trace
+            && !TRACE_WARNED.replace(true)
+        {
+            self.write(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("ic_logger")
+                    .args(format_args!("trace logging is enabled, which is costly on-chain"))
+                    .build(),
+            );
+        }
+        for warning in &self.parse_warnings {
+            self.write(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("ic_logger")
+                    .args(format_args!("{warning}"))
+                    .build(),
+            );
+        }
+    }
+
+    /// Set the default level, or the level of `target` if given.
+    fn apply_directive(&mut self, target: Option<&str>, level: LevelFilter) {
+        match target {
+            Some(target) => self.filter.add_module_level(target, level),
+            None => self.filter.set_default_level(level),
+        }
+    }
+
+    /// Apply the lenient fallback, if any, to the directives with unknown level names.
+    fn resolve_invalid_directives(&mut self) {
+        let Some(fallback) = self.lenient_fallback else {
+            return;
+        };
+        for (target, token) in std::mem::take(&mut self.invalid_directives) {
+            self.apply_directive(target.as_deref(), fallback);
+            let directive = match &target {
+                Some(target) => format!("{target}={token}"),
+                None => token,
+            };
+            self.parse_warnings
+                .push(format!("unknown level in `{directive}`, using {fallback} instead"));
+        }
+    }
+
+    /// The most verbose level the logger may log at.
+    fn max_level(&self) -> LevelFilter {
+        let max_level = if self.always_log.is_empty() && self.level_fns.is_empty() {
+            self.filter.max_level().max(self.unfiltered_level())
+        } else {
+            LevelFilter::Trace
+        };
+        max_level.min(log::STATIC_MAX_LEVEL)
+    }
+
+    /// Check the parts of the configuration that can only be reported once it's complete.
+    fn validate(&self) -> Result<(), Error> {
+        if let Some((_target, token)) = self.invalid_directives.first() {
+            return Err(Error::InvalidLevel(token.clone()));
+        }
+        let count = self.filter.module_level_count() + self.level_fns.len();
+        match self.max_rules {
+            Some(max) if count > max => Err(Error::TooManyRules { count, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a record of `target` at `level` against the level function of the target, if any, or
+    /// the static levels otherwise.
+    fn target_enabled(&self, target: &str, level: Level, static_levels: impl FnOnce() -> bool) -> bool {
+        match self
+            .level_fns
+            .iter()
+            .find(|(prefix, _f)| target.starts_with(prefix.as_str()))
+        {
+            /* Both are sorted from most to least specific, so the longer of the two first matches
+             * is the most specific rule overall.
+             */
+            Some((prefix, f))
+                if self
+                    .filter
+                    .module_for(target)
+                    .is_none_or(|module| module.len() <= prefix.len()) =>
+            {
+                level <= f()
+            }
+            _ => static_levels(),
+        }
+    }
+
+    /// The level at and above which records are logged regardless of `filter`.
+    fn unfiltered_level(&self) -> LevelFilter {
+        self.passthrough.max(self.floor)
+    }
+
+    /// Check whether a record passes all filters that don't depend on previous records.
+    fn passes(&self, record: &Record) -> bool {
+        self.enabled(record.metadata())
+            && (record.key_values().count() > 0
+                || !self.require_kv.iter().any(|prefix| record.target().starts_with(prefix)))
+    }
+
+    /// Check the conditions that apply to records of every target.
+    fn allows(&self, level: Level) -> bool {
+        /* Records above the static max level of `log` are compiled out, claiming otherwise would
+         * only mislead callers checking `log_enabled!` by hand.
+         */
+        if level > log::STATIC_MAX_LEVEL || self.muted {
+            return false;
+        }
+        if self.update_only && (self.in_query)() {
+            return false;
+        }
+        if self
+            .cycles_gate
+            .is_some_and(|min_remaining| (self.balance)() < min_remaining)
+        {
+            return false;
+        }
+        if let Some((start, end, quiet_level)) = self.quiet_window {
+            let time_of_day = (self.clock)() % NANOS_PER_DAY;
+            let quiet = if start <= end {
+                (start..end).contains(&time_of_day)
+            } else {
+                time_of_day >= start || time_of_day < end
+            };
+            if quiet && level > quiet_level {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether records of `target` are logged regardless of the filter.
+    fn always_logged(&self, target: &str) -> bool {
+        self.always_log.iter().any(|prefix| target.starts_with(prefix))
+    }
+
+    /// Format a record into the line that gets printed.
+    #[cfg(test)]
+    fn format_record(&self, record: &Record) -> String {
+        self.format.format(record, self.timestamp(record))
+    }
+
+    /// The timestamp of the line rendered from `record`, if it gets one.
+    fn timestamp(&self, record: &Record) -> Option<u64> {
+        if !cfg!(feature = "timestamps") {
+            return None;
+        }
+        if self.timestamps
+            || self
+                .timestamp_targets
+                .iter()
+                .any(|prefix| record.target().starts_with(prefix))
+        {
+            Some((self.clock)())
+        } else {
+            None
+        }
+    }
+
+    /// Lock the lines captured by [`capture`].
+    fn captured(&self) -> MutexGuard<'_, Option<Vec<(Level, String)>>> {
+        self.captured.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The header of exports of the buffer, if enabled.
+    fn export_header(&self) -> Option<String> {
+        if !self.export_header {
+            return None;
+        }
+        let mut header = format!("{{\"v\":{},\"kind\":\"header\"", self.format.json_schema_version);
+        if cfg!(target_arch = "wasm32") {
+            header.push_str(&format!(",\"canister\":{}", json_string(&ic_cdk::id().to_text())));
+        }
+        if let Some(session) = self.session {
+            header.push_str(&format!(",\"session\":{session}"));
+        }
+        header.push('}');
+        Some(header)
+    }
+
+    /// The buffer the records of `target` are kept in: the first named buffer whose prefix it
+    /// starts with or the default one, if any.
+    fn buffer_for(&self, target: &str) -> Option<&Mutex<Buffer>> {
+        self.named_buffers
+            .iter()
+            .find(|(prefix, _buffer)| target.starts_with(prefix.as_str()))
+            .map(|(_prefix, buffer)| buffer)
+            .or(self.buffer.as_ref())
+    }
+
+    /// Lock the buffer, if buffering is enabled.
+    fn buffer(&self) -> Option<MutexGuard<'_, Buffer>> {
+        self.buffer
+            .as_ref()
+            .map(|buffer| buffer.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Default for IcLogger {
+    /// See [this](struct.IcLogger.html#method.new)
+    fn default() -> Self {
+        IcLogger::new()
+    }
+}
+
+impl Log for IcLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.allows(metadata.level())
+            && (metadata.level() <= self.unfiltered_level()
+                || self.always_logged(metadata.target())
+                || metadata.target().starts_with(FORCE_PREFIX)
+                || self.target_enabled(metadata.target(), metadata.level(), || self.filter.enabled(metadata)))
+            && self.filter_fn.as_ref().is_none_or(|filter| filter(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        self.see_target(record.target());
+        if !self.passes(record) {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let sampled = self
+            .samplers
+            .iter()
+            .find(|(prefix, _sampler)| record.target().starts_with(prefix.as_str()));
+        if sampled.is_some_and(|(_prefix, sampler)| !sampler.allow()) {
+            return;
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            if !rate_limit.allow((self.clock)()) {
+                return;
+            }
+            self.write_rate_limit_summary(rate_limit);
+        }
+        if let Some(budget) = self.output_budget.as_ref().filter(|budget| budget.exhausted()) {
+            if budget.take_notice() {
+                self.write(
+                    &Record::builder()
+                        .level(Level::Warn)
+                        .target("ic_logger")
+                        .args(format_args!(
+                            "output budget of {} bytes exhausted, logging stopped",
+                            budget.max()
+                        ))
+                        .build(),
+                );
+            }
+            return;
+        }
+        if let Some(target) = record.target().strip_prefix(FORCE_PREFIX) {
+            return self.emit(&record.to_builder().target(target).build());
+        }
+        self.emit(record);
+    }
+
+    fn flush(&self) {
+        if let Some(summary) = self.dedup.as_ref().and_then(Dedup::take_summary) {
+            self.write_summary(&summary);
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            self.write_rate_limit_summary(rate_limit);
+        }
+
+        if let Some(pending) = &self.pending {
+            let lines = std::mem::take(&mut *pending.lock().unwrap_or_else(PoisonError::into_inner));
+            if !lines.is_empty() {
+                self.backend.print(&lines.join("\n"), self.print);
+            }
+        }
+    }
+}
+
+impl IcLogger {
+    /// Take the values that are fixed once the logger is installed: the time relative timestamps
+    /// count from, see [`TimestampFormat::SinceInit`], and the session id.
+    fn start(&mut self) {
+        if let Some(session) = &mut self.session {
+            *session = (self.clock)();
+        }
+        if cfg!(feature = "timestamps") && self.format.timestamp_format == TimestampFormat::SinceInit {
+            self.format.timestamp_baseline = (self.clock)();
+        }
+    }
+
+    /// Write an enabled record, unless it's a repeat dropped by dedup.
+    fn emit(&self, record: &Record) {
+        if let Some(dedup) = &self.dedup {
+            match dedup.check(record.level(), record.target(), &record.args().to_string()) {
+                Seen::Repeat => return,
+                Seen::New(summary) => summary.into_iter().for_each(|summary| self.write_summary(&summary)),
+            }
+        }
+
+        match self.chunk_size {
+            Some(max_bytes) => self.write_chunked(record, max_bytes),
+            None => self.write(record),
+        }
+    }
+
+    /// Write a record as one line per part of its message that fits into `max_bytes`.
+    fn write_chunked(&self, record: &Record, max_bytes: usize) {
+        let message = record.args().to_string();
+        if message.len() <= max_bytes {
+            return self.write(record);
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = message.as_str();
+        while !rest.is_empty() {
+            let end = (1..=max_bytes.min(rest.len()))
+                .rev()
+                .find(|end| rest.is_char_boundary(*end))
+                .unwrap_or_else(|| rest.chars().next().map_or(0, char::len_utf8));
+            let (part, remaining) = rest.split_at(end);
+            parts.push(part);
+            rest = remaining;
+        }
+        for (index, part) in parts.iter().enumerate() {
+            self.write(
+                &record
+                    .to_builder()
+                    .args(format_args!("{part} (part {}/{})", index + 1, parts.len()))
+                    .build(),
+            );
+        }
+    }
+
+    /// Write the summary of dropped records.
+    fn write_summary(&self, summary: &Summary) {
+        self.write(
+            &Record::builder()
+                .level(self.summary_level.unwrap_or(summary.level))
+                .target(&summary.target)
+                .args(format_args!("{}", summary.message()))
+                .build(),
+        );
+    }
+
+    /// Write the summary of the records dropped by the rate limit since the last one, if any.
+    fn write_rate_limit_summary(&self, rate_limit: &RateLimiter) {
+        let dropped = rate_limit.take_unreported();
+        if dropped == 0 {
+            return;
+        }
+        self.write(
+            &Record::builder()
+                .level(self.summary_level.unwrap_or(Level::Warn))
+                .target("ic_logger")
+                .args(format_args!(
+                    "{dropped} {} dropped by the rate limit",
+                    if dropped == 1 { "record" } else { "records" }
+                ))
+                .build(),
+        );
+    }
+
+    /// Write a record that passed all filters to all outputs.
+    fn write(&self, record: &Record) {
+        self.with_fields(record, true, |record| self.write_record(record));
+    }
+
+    /// Run `f` with `record` extended by the fields the logger adds to every line.
+    ///
+    /// Taking the next number of the logical clock is a side effect, which is skipped unless
+    /// `advance` is set.
+    fn with_fields<R>(&self, record: &Record, advance: bool, f: impl FnOnce(&Record) -> R) -> R {
+        let sequence = self.sequence.as_ref().map(|sequence| match advance {
+            true => sequence.next((self.clock)()),
+            false => sequence.peek((self.clock)()),
+        });
+        let extra: Vec<_> = self
+            .stable_size
+            .map(|size| ("stable_size", size()))
+            .into_iter()
+            .chain(
+                self.instructions_remaining
+                    .map(|limit| ("rem", limit.saturating_sub((self.instruction_counter)()))),
+            )
+            .chain(self.session.map(|session| ("session", session)))
+            .chain(sequence.map(|sequence| ("seq", sequence)))
+            .collect();
+        let mut context = context_fields();
+        if let Some(correlation) = &self.correlation {
+            context.push(("cid".to_string(), correlation.id((self.clock)())));
+        }
+        if context.is_empty() && extra.is_empty() {
+            return f(record);
+        }
+
+        let fields = Fields {
+            record: record.key_values(),
+            context,
+            extra,
+        };
+        f(&record.to_builder().key_values(&fields).build())
+    }
+
+    /// Write a record with all of its fields in place.
+    fn write_record(&self, record: &Record) {
+        let timestamp = self.timestamp(record);
+        let Some(line) = self.intercept(self.format.format(record, timestamp)) else {
+            return;
+        };
+        if let Some(budget) = &self.output_budget {
+            budget.spend(line.len() as u64);
+        }
+        if !self.write_target_sinks(record, &line) {
+            return;
+        }
+        self.write_sinks(record, timestamp, &line);
+
+        /* The target and line are only copied when an entry is kept after printing */
+        let buffer = self
+            .buffer_for(record.target())
+            .filter(|_| record.level() <= self.buffer_level);
+        if buffer.is_none() && self.forwarder.is_none() {
+            return self.write_console(record.level(), Cow::Owned(line));
+        }
+        self.write_console(record.level(), Cow::Borrowed(&line));
+        let entry = LogEntry {
+            level: record.level(),
+            target: record.target().to_string(),
+            line,
+        };
+        if let Some(forwarder) = &self.forwarder {
+            let throttled = self.forward_throttle.as_ref().is_some_and(|throttle| {
+                let message = record.args().to_string();
+                !throttle.allow(record.level(), record.target(), &message, (self.clock)())
+            });
+            if !throttled {
+                if let Err(error) = forwarder.forward(&entry) {
+                    self.error_callbacks.iter().for_each(|callback| callback(&error));
+                }
+            }
+        }
+        if let Some(buffer) = buffer {
+            let mut buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+            buffer.push(entry, self.oversized_line_policy);
+        }
+    }
+
+    /// Print, capture or batch a line, depending on the configuration.
+    fn write_console(&self, level: Level, line: Cow<'_, str>) {
+        let line = match self.stream(level) {
+            Some(Stream::Stderr) => Cow::Owned(format!("{STDERR_MARKER}{line}")),
+            _ => line,
+        };
+        if level > self.console_level {
+            /* Only kept in memory */
+        } else if let Some(captured) = self.captured().as_mut() {
+            captured.push((level, line.into_owned()));
+        } else if let Some(pending) = &self.pending {
+            let mut pending = pending.lock().unwrap_or_else(PoisonError::into_inner);
+            pending.push(line.into_owned());
+            if self.batch_flush_level.is_some_and(|flush_level| level <= flush_level) {
+                self.backend
+                    .print(&std::mem::take(&mut *pending).join("\n"), self.print);
+            }
+        } else {
+            self.backend.print(&line, self.print);
+        }
+    }
+
+    /// The stream records at `level` are written to, if streams are split.
+    fn stream(&self, level: Level) -> Option<Stream> {
+        self.split_streams.then(|| Stream::for_level(level))
+    }
+
+    /// Write the line rendered from `record` to a sink, to the stream for its level if they are
+    /// split.
+    fn write_sink(&self, sink: &dyn Sink, record: &Record, line: &str) {
+        match self.stream(record.level()) {
+            Some(stream) => sink.write_stream(stream, record.level(), record.target(), line),
+            None => sink.write(record.level(), record.target(), line),
+        }
+    }
+
+    /// Write the line rendered from `record` to the sinks bound to its target.
+    ///
+    /// Returns whether the line goes to the other outputs too.
+    fn write_target_sinks(&self, record: &Record, line: &str) -> bool {
+        let mut default = true;
+        for (prefix, sink, and_default) in &self.target_sinks {
+            if record.target().starts_with(prefix.as_str()) {
+                self.write_sink(sink.as_ref(), record, line);
+                default &= *and_default;
+            }
+        }
+        default
+    }
+
+    /// Write the line rendered from `record` to all sinks, rendering it once per distinct format.
+    fn write_sinks(&self, record: &Record, timestamp: Option<u64>, line: &str) {
+        /* Sinks in the configured format share `line`, only other formats are rendered again */
+        let mut others: Vec<(Format, Option<String>)> = Vec::new();
+        for (sink, format) in &self.sinks {
+            let format = format.unwrap_or(self.format.kind);
+            if format == self.format.kind {
+                self.write_sink(sink.as_ref(), record, line);
+                continue;
+            }
+            let index = match others.iter().position(|(kind, _)| *kind == format) {
+                Some(index) => index,
+                None => {
+                    let line = self.intercept(self.format.format_as(format, record, timestamp));
+                    others.push((format, line));
+                    others.len() - 1
+                }
+            };
+            if let Some(line) = &others[index].1 {
+                self.write_sink(sink.as_ref(), record, line);
+            }
+        }
+    }
+
+    /// Run the line interceptor, if any, on a formatted line.
+    fn intercept(&self, line: String) -> Option<String> {
+        match &self.interceptor {
+            Some(interceptor) => interceptor(line),
+            None => Some(line),
+        }
+    }
+}
+
+/// The current time in nanoseconds since the unix epoch, outside of a canister the system time.
+fn time() -> u64 {
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::time()
+    } else {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos().try_into().unwrap_or(u64::MAX))
+    }
+}
+
+/// Check whether the current message is a (non-replicated) query call.
+///
+/// A data certificate is only available in query calls, any other context, including the ones
+/// outside of a call, reports none.
+fn in_query_call() -> bool {
+    cfg!(target_arch = "wasm32") && ic_cdk::api::data_certificate().is_some()
+}
+
+/// Write a line to the debug output of the canister, outside of a canister to stdout.
+fn debug_print(line: &str) {
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::print(line);
+    } else {
+        println!("{line}");
+    }
+}
+
+/// The cycle balance of the canister, outside of a canister there is no limit.
+fn canister_balance() -> u128 {
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::canister_balance128()
+    } else {
+        u128::MAX
+    }
+}
+
+/// The size of stable memory in bytes, outside of a canister there is none.
+fn stable_size() -> u64 {
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::stable::stable64_size() * 65536
+    } else {
+        0
+    }
+}
+
+/// The instructions executed by the current message, counted by the installed logger if any.
+pub(crate) fn instructions() -> u64 {
+    with_logger(|logger| (logger.instruction_counter)()).unwrap_or_else(instruction_counter)
+}
+
+/// The instructions executed by the current message, outside of a canister there are none.
+fn instruction_counter() -> u64 {
+    if cfg!(target_arch = "wasm32") {
+        ic_cdk::api::performance_counter(0)
+    } else {
+        0
+    }
+}
+
+/// The fields of a record, followed by the context fields and the ones the logger adds.
+struct Fields<'a> {
+    record: &'a dyn Source,
+    context: Vec<(String, String)>,
+    extra: Vec<(&'static str, u64)>,
+}
+
+impl Source for Fields<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), kv::Error> {
+        self.record.visit(visitor)?;
+        for (key, value) in &self.context {
+            visitor.visit_pair(Key::from(key.as_str()), Value::from(value.as_str()))?;
+        }
+        for (key, value) in &self.extra {
+            visitor.visit_pair(Key::from(*key), Value::from(*value))?;
+        }
+        Ok(())
+    }
+}
+
+struct InstalledLogger;
+
+impl Log for InstalledLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        with_logger(|logger| logger.enabled(metadata)).unwrap_or(false)
+    }
+
+    fn log(&self, record: &Record) {
+        with_logger(|logger| logger.log(record));
+    }
+
+    fn flush(&self) {
+        with_logger(|logger| logger.flush());
+    }
+}
+
+/// Run `f` with the installed logger, returning `None` if there is none.
+fn with_logger<R>(f: impl FnOnce(&IcLogger) -> R) -> Option<R> {
+    LOGGER.with(|installed| installed.try_borrow().ok()?.as_ref().map(f))
+}
+
+/// Run `f` with the installed logger mutably, returning `None` if there is none.
+///
+/// The levels may change, so the max level of `log` is updated afterwards.
+fn with_logger_mut<R>(f: impl FnOnce(&mut IcLogger) -> R) -> Option<R> {
+    LOGGER.with(|installed| {
+        let mut installed = installed.try_borrow_mut().ok()?;
+        let logger = installed.as_mut()?;
+        let result = f(logger);
+        log::set_max_level(logger.max_level());
+        Some(result)
+    })
+}
+
+/// Initialise the logger with its default configuration.
+///
+/// Log messages will not be filtered.
+/// The `RUST_LOG` environment variable is not used.
+pub fn init() -> Result<(), Error> {
+    IcLogger::new().init()
+}
+
+/// Initialise the logger with a specific log level.
+///
+/// Log messages below the given [`Level`] will be filtered.
+/// The `RUST_LOG` environment variable is not used.
+pub fn init_with_level(level: Level) -> Result<(), Error> {
+    IcLogger::new().with_level(level.to_level_filter()).init()
+}
+
+/// Initialise the logger with the levels of a directive string, see [`IcLogger::with_directives`].
+///
+/// This suits canisters that take their logging configuration as an argument:
+///
+/// ```no_run
+/// #[ic_cdk::init]
+/// fn init(log_spec: String) {
+///     ic_logger::init_from_str(&log_spec).unwrap();
+/// }
+/// ```
+///
+/// Fails with [`Error::InvalidLevel`] without installing the logger if a directive has an unknown
+/// level name.
+pub fn init_from_str(spec: &str) -> Result<(), Error> {
+    IcLogger::new().with_directives(spec).init()
+}
+
+/// Initialise the logger with a default level and per-module levels in a single call.
+///
+/// This is a shorthand for building an [`IcLogger`] with [`IcLogger::with_level`] and
+/// [`IcLogger::with_module_level`] for each of the `modules`.
+///
+/// ```no_run
+/// use log::LevelFilter;
+///
+/// ic_logger::quick(LevelFilter::Warn, &[("my_canister", LevelFilter::Debug)]).unwrap();
+/// ```
+pub fn quick(level: LevelFilter, modules: &[(&str, LevelFilter)]) -> Result<(), Error> {
+    modules
+        .iter()
+        .fold(IcLogger::new().with_level(level), |logger, (target, level)| {
+            logger.with_module_level(target, *level)
+        })
+        .init()
+}
+
+/// Make every target of the installed logger log at least as verbosely as `level`.
+///
+/// This is an emergency switch, e.g. to turn everything up to [`LevelFilter::Debug`] while
+/// investigating an incident, that overrides more restrictive default and module levels. Setting
+/// the floor to [`LevelFilter::Off`] removes it again.
+///
+/// Does nothing if the logger was not initialised yet.
+pub fn set_floor_level(level: LevelFilter) {
+    with_logger_mut(|logger| logger.floor = level);
+}
+
+/// Drop all records of the installed logger while `suppressed` is set, whatever their level.
+///
+/// This is coarser than level filtering and meant to silence noisy phases, e.g. around lifecycle
+/// hooks:
+///
+/// ```no_run
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     ic_logger::set_suppressed(true);
+///     // restore the state
+///     ic_logger::set_suppressed(false);
+/// }
+/// ```
+///
+/// Does nothing if the logger was not initialised yet.
+pub fn set_suppressed(suppressed: bool) {
+    with_logger_mut(|logger| logger.muted = suppressed);
+}
+
+/// The number of records dropped by the rate limit of the installed logger.
+///
+/// Returns 0 if the logger was not initialised yet, see [`IcLogger::with_rate_limit`].
+pub fn rate_limited_count() -> u64 {
+    with_logger(IcLogger::rate_limited_count).unwrap_or(0)
+}
+
+/// The number of records filtered out by the installed logger, see [`IcLogger::suppressed_count`].
+///
+/// Returns 0 if the logger was not initialised yet.
+pub fn suppressed_count() -> u64 {
+    with_logger(IcLogger::suppressed_count).unwrap_or(0)
+}
+
+/// The number of lines the buffer of the installed logger dropped for being longer than its byte
+/// cap, see [`OversizedLinePolicy::DropAndCount`].
+///
+/// Returns 0 if the logger was not initialised yet or buffering is disabled.
+pub fn oversized_count() -> u64 {
+    with_logger(|logger| logger.buffer().map(|buffer| buffer.oversized()))
+        .flatten()
+        .unwrap_or(0)
+}
+
+/// The targets of the records logged so far, in alphabetical order, see [`IcLogger::seen_targets`].
+///
+/// Returns nothing if the logger was not initialised yet.
+pub fn seen_targets() -> Vec<String> {
+    with_logger(IcLogger::seen_targets).unwrap_or_default()
+}
+
+/// Start a new correlation id for the lines logged from now on, see [`IcLogger::with_auto_correlation`].
+///
+/// ```no_run
+/// #[ic_cdk::update]
+/// fn transfer() {
+///     ic_logger::reset_correlation_id();
+///     log::info!("transferring");
+/// }
+/// ```
+///
+/// Does nothing if the logger was not initialised yet or correlation ids are disabled.
+pub fn reset_correlation_id() {
+    with_logger(|logger| logger.correlation.as_ref().map(Correlation::reset));
+}
+
+/// Install `config` as the logger, or replace the installed logger with it.
+///
+/// A canister has to set up logging in both its `init` and `post_upgrade` hooks, this lets both
+/// of them call the same function without failing on a logger that is already installed:
+///
+/// ```no_run
+/// use ic_logger::IcLogger;
+/// use log::LevelFilter;
+///
+/// fn setup_logging() {
+///     ic_logger::ensure_installed(IcLogger::new().with_level(LevelFilter::Info)).unwrap();
+/// }
+///
+/// #[ic_cdk::init]
+/// fn init() {
+///     setup_logging();
+/// }
+///
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     setup_logging();
+/// }
+/// ```
+///
+/// Replacing the installed logger drops its state, like the buffered entries and the lines held
+/// back by batching, apart from the targets registered with [`intern_target`]. Fails like [`IcLogger::init`] if a different logger was registered with the
+/// `log` crate, or with [`Error::AlreadyInitialized`] if called while the installed logger is
+/// writing a record.
+pub fn ensure_installed(mut config: IcLogger) -> Result<(), Error> {
+    if !is_initialized() {
+        return config.init();
+    }
+    config.validate()?;
+    config.start();
+    config.announce();
+    with_logger_mut(|logger| {
+        config.filter.adopt_interned(&logger.filter);
+        *logger = config;
+    })
+    .ok_or(Error::AlreadyInitialized)
+}
+
+/// Check whether a logger was installed with [`IcLogger::init`] or one of its shorthands.
+///
+/// This lets frameworks skip a second initialisation, or buffer their own records until the
+/// canister installs a logger.
+///
+/// Like the installed logger itself, this is tracked per thread. Canisters run on a single
+/// thread, where it's accurate. Outside of a canister, other threads report `false` until they
+/// install a logger of their own, even though the proxy returned by [`log::logger`] is registered
+/// for the whole process. Until then their records are dropped.
+pub fn is_initialized() -> bool {
+    INSTALLED.get()
+}
+
+/// Check whether the installed logger would log a record with the given target and level.
+///
+/// Returns `false` if the logger was not initialised yet.
+pub fn would_log(target: &str, level: Level) -> bool {
+    let metadata = Metadata::builder().target(target).level(level).build();
+    with_logger(|logger| logger.enabled(&metadata)).unwrap_or(false)
+}
+
+/// Check whether the installed logger would keep a record in its buffer, see
+/// [`IcLogger::would_buffer`].
+///
+/// Returns `false` if the logger was not initialised yet.
+pub fn would_buffer(metadata: &Metadata) -> bool {
+    with_logger(|logger| logger.would_buffer(metadata)).unwrap_or(false)
+}
+
+/// Register a target with the installed logger, see [`IcLogger::intern_target`].
+///
+/// The id stays valid when the installed logger is replaced with [`ensure_installed`], which
+/// carries the interned targets over. Returns `None` if the logger was not initialised yet.
+///
+/// ```no_run
+/// use log::Level;
+///
+/// ic_logger::init().unwrap();
+/// let hot = ic_logger::intern_target("my_canister::hot").unwrap();
+/// for item in 0..1000 {
+///     if ic_logger::would_log_id(hot, Level::Debug) {
+///         log::debug!(target: "my_canister::hot", "processing {item}");
+///     }
+/// }
+/// ```
+pub fn intern_target(target: &str) -> Option<TargetId> {
+    with_logger_mut(|logger| logger.intern_target(target))
+}
+
+/// Check whether the installed logger would log a record of an interned target at `level`.
+///
+/// Returns `false` if the logger was not initialised yet.
+///
+/// # Panics
+///
+/// Panics if `id` was not interned with [`intern_target`], e.g. by an [`IcLogger`] before it was
+/// installed.
+pub fn would_log_id(id: TargetId, level: Level) -> bool {
+    with_logger(|logger| logger.enabled_id(id, level)).unwrap_or(false)
+}
+
+/// Take a snapshot of the levels of the installed logger, see [`IcLogger::snapshot`].
+///
+/// Returns `None` if the logger was not initialised yet.
+pub fn snapshot() -> Option<ConfigSnapshot> {
+    with_logger(IcLogger::snapshot)
+}
+
+/// Describe the configuration of the installed logger, see [`IcLogger::describe`].
+///
+/// Returns `None` if the logger was not initialised yet.
+pub fn describe() -> Option<String> {
+    with_logger(IcLogger::describe)
+}
+
+/// Reapply the levels of a [`snapshot`] to the installed logger.
+///
+/// This undoes any changes to the levels made since the snapshot was taken, e.g. to debug an
+/// issue at runtime:
+///
+/// ```no_run
+/// let levels = ic_logger::snapshot().unwrap();
+/// // change the levels and investigate
+/// ic_logger::restore(levels);
+/// ```
+///
+/// Does nothing if the logger was not initialised yet.
+pub fn restore(snapshot: ConfigSnapshot) {
+    with_logger_mut(|logger| logger.restore(snapshot));
+}
+
+/// Get all buffered entries, from the oldest to the most recent one.
+///
+/// Returns nothing if the logger was not initialised or buffering is disabled, see
+/// [`IcLogger::with_buffer`].
+pub fn get_logs() -> Vec<LogEntry> {
+    with_logger(|logger| Some(logger.buffer()?.entries().cloned().collect()))
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Get the most recent buffered entry of exactly `level`, e.g. the last error for a dashboard.
+///
+/// Unlike [`tail`], more severe entries don't count. Returns `None` if there is no such entry, the
+/// logger was not initialised or buffering is disabled.
+pub fn last_entry_at_level(level: Level) -> Option<LogEntry> {
+    with_logger(|logger| {
+        logger
+            .buffer()?
+            .entries()
+            .rev()
+            .find(|entry| entry.level == level)
+            .cloned()
+    })
+    .flatten()
+}
+
+/// Count the buffered entries for which `f` returns true.
+///
+/// This answers questions like "how many errors are buffered?" without copying the entries:
+///
+/// ```no_run
+/// use log::Level;
+///
+/// #[ic_cdk::query]
+/// fn error_count() -> usize {
+///     ic_logger::count_entries(|entry| entry.level == Level::Error)
+/// }
+/// ```
+///
+/// Returns 0 if the logger was not initialised or buffering is disabled.
+pub fn count_entries(f: impl Fn(&LogEntry) -> bool) -> usize {
+    with_logger(|logger| Some(logger.buffer()?.entries().filter(|entry| f(entry)).count()))
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Get all entries of the named buffer for `target_prefix`, from the oldest to the most recent one.
+///
+/// Returns nothing if the logger was not initialised or has no such buffer, see
+/// [`IcLogger::with_named_buffer`].
+pub fn get_named_logs(target_prefix: &str) -> Vec<LogEntry> {
+    with_logger(|logger| {
+        let (_prefix, buffer) = logger
+            .named_buffers
+            .iter()
+            .find(|(prefix, _buffer)| prefix == target_prefix)?;
+        let buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(buffer.entries().cloned().collect())
+    })
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Get the buffered lines as a single newline separated text, from the oldest to the most recent one.
+///
+/// The text starts with the header of [`IcLogger::with_export_header`], if enabled. Returns an
+/// empty text if the logger was not initialised or buffering is disabled.
+pub fn get_logs_text() -> String {
+    with_logger(|logger| {
+        let header = logger.export_header();
+        Some(logger.buffer()?.chunks(usize::MAX, false, header.as_deref()).concat())
+    })
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Get the lines of up to `n` of the most recent buffered entries at or above `min`, from the
+/// oldest to the most recent one.
+///
+/// This suits an admin query method that shows what went wrong lately:
+///
+/// ```no_run
+/// use log::Level;
+///
+/// #[ic_cdk::query]
+/// fn recent_errors() -> Vec<String> {
+///     ic_logger::tail(20, Level::Error)
+/// }
+/// ```
+///
+/// Returns nothing if the logger was not initialised or buffering is disabled.
+pub fn tail(n: usize, min: Level) -> Vec<String> {
+    with_logger(|logger| {
+        let buffer = logger.buffer()?;
+        let mut lines: Vec<_> = buffer
+            .entries()
+            .rev()
+            .filter(|entry| entry.level <= min)
+            .take(n)
+            .map(|entry| entry.line.clone())
+            .collect();
+        lines.reverse();
+        Some(lines)
+    })
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Get all buffered entries, from the most recent to the oldest one.
+///
+/// This is [`get_logs`] in reverse, for UIs showing the newest entries first.
+///
+/// Returns nothing if the logger was not initialised or buffering is disabled.
+pub fn get_entries_newest_first() -> Vec<LogEntry> {
+    with_logger(|logger| Some(logger.buffer()?.entries().rev().cloned().collect()))
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Put entries back into the buffer of the installed logger, e.g. the ones saved before an upgrade.
+///
+/// The entries are added after the ones already buffered, from the first to the last one, and
+/// evict the oldest entries like logged ones do.
+///
+/// ```no_run
+/// #[ic_cdk::pre_upgrade]
+/// fn pre_upgrade() {
+///     ic_cdk::storage::stable_save((ic_logger::get_logs(),)).unwrap();
+/// }
+///
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     ic_logger::IcLogger::new().with_buffer(100).init().unwrap();
+///     let (entries,) = ic_cdk::storage::stable_restore().unwrap();
+///     ic_logger::restore_logs(entries);
+/// }
+/// ```
+///
+/// Does nothing if the logger was not initialised yet or buffering is disabled.
+pub fn restore_logs(entries: Vec<LogEntry>) {
+    with_logger(|logger| {
+        if let Some(mut buffer) = logger.buffer() {
+            entries
+                .into_iter()
+                .for_each(|entry| buffer.push(entry, logger.oversized_line_policy));
+        }
+    });
+}
+
+/// Remove and return all buffered entries, from the oldest to the most recent one.
+///
+/// This is the "fetch and reset" of pull-based log shipping: unlike calling [`get_logs`] and
+/// clearing the buffer afterwards, no entry logged in between can get lost.
+///
+/// Returns nothing if the logger was not initialised or buffering is disabled.
+pub fn drain() -> Vec<LogEntry> {
+    with_logger(|logger| Some(logger.buffer()?.drain()))
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Get the buffered lines as newline separated chunks of at most `max_bytes` each.
+///
+/// A response of a canister is limited in size, so this allows a paginated query method to
+/// return the buffered lines chunk by chunk. Lines are never split across chunks, a single line
+/// longer than `max_bytes` gets a chunk of its own.
+///
+/// ```no_run
+/// #[ic_cdk::query]
+/// fn logs(page: usize) -> Option<String> {
+///     ic_logger::export_chunks(1_000_000).into_iter().nth(page)
+/// }
+/// ```
+///
+/// Returns nothing if the logger was not initialised or buffering is disabled.
+pub fn export_chunks(max_bytes: usize) -> Vec<String> {
+    with_logger(|logger| {
+        let header = logger.export_header();
+        Some(logger.buffer()?.chunks(max_bytes, false, header.as_deref()))
+    })
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Get the buffered lines as chunks like [`export_chunks`], from the most recent to the oldest one.
+///
+/// This suits UIs showing the newest lines first, the first page holds the most recent lines.
+/// Returns nothing if the logger was not initialised or buffering is disabled.
+pub fn export_chunks_newest_first(max_bytes: usize) -> Vec<String> {
+    with_logger(|logger| {
+        let header = logger.export_header();
+        Some(logger.buffer()?.chunks(max_bytes, true, header.as_deref()))
+    })
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Log a message under the given target, regardless of the calling module.
+///
+/// This is useful for grouping logs under a logical category, like the `target:` argument of the
+/// [`log!`](log::log) macro. The record is filtered by the target's level as usual.
+///
+/// ```no_run
+/// use log::Level;
+///
+/// ic_logger::log_to("payments", Level::Info, format_args!("charged {} cycles", 100));
+/// ```
+pub fn log_to(target: &str, level: Level, args: fmt::Arguments) {
+    log::logger().log(&Record::builder().target(target).level(level).args(args).build());
+}
+
+/// Log a message given as bytes under the given target, see [`log_to`].
+///
+/// Bytes that are not valid UTF-8 are escaped, e.g. `\xff`, or replaced if the installed logger
+/// was built with [`IcLogger::with_lossy_utf8`], so they never reach the outputs as they are:
+///
+/// ```no_run
+/// use log::Level;
+///
+/// ic_logger::log_bytes("codec", Level::Warn, b"bad frame \xff\x00");
+/// ```
+pub fn log_bytes(target: &str, level: Level, bytes: &[u8]) {
+    let message = match with_logger(|logger| logger.lossy_utf8) {
+        Some(true) => String::from_utf8_lossy(bytes),
+        _ => escape_invalid_utf8(bytes),
+    };
+    log_to(target, level, format_args!("{message}"));
+}
+
+/// Escape the bytes that are not valid UTF-8 as `\xNN`, borrowing valid text as it is.
+fn escape_invalid_utf8(bytes: &[u8]) -> Cow<'_, str> {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Cow::Borrowed(text);
+    }
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    Cow::Owned(text)
+}
+
+/// Log an error and the chain of its causes under the given target.
+///
+/// The error and each of its [`source`](std::error::Error::source)s are logged as their own line,
+/// JSON lines keep the causes in the line of the error instead, under `causes`:
+///
+/// ```text
+/// [ERROR payments] transfer failed
+/// [ERROR payments] caused by: ledger rejected the call
+/// ```
+///
+/// ```text
+/// {"v":2,"level":"ERROR","target":"payments","msg":"transfer failed","causes":["ledger rejected the call"]}
+/// ```
+pub fn log_error(target: &str, error: &dyn std::error::Error) {
+    let causes: Vec<_> = std::iter::successors(error.source(), |cause| cause.source())
+        .map(|cause| (CAUSES_KEY, cause.to_string()))
+        .collect();
+    let json = with_logger(|logger| {
+        matches!(
+            logger.format.kind,
+            Format::Json | Format::JsonArrayElement | Format::Ndjson
+        )
+    });
+    if json == Some(true) {
+        return log::logger().log(
+            &Record::builder()
+                .target(target)
+                .level(Level::Error)
+                .args(format_args!("{error}"))
+                .key_values(&causes)
+                .build(),
+        );
+    }
+
+    log_to(target, Level::Error, format_args!("{error}"));
+    for (_key, cause) in &causes {
+        log_to(target, Level::Error, format_args!("caused by: {cause}"));
+    }
+}
+
+/// Log a message under the given target only the first time `key` is used.
+///
+/// Later calls with the same key are suppressed for the lifetime of the installed logger, even if
+/// their message differs. The key can be anything identifying the call site, like a static string
+/// or `file:line`. The per-level macros, like [`warn_once!`](crate::warn_once!), use the calling
+/// module as the target:
+///
+/// ```no_run
+/// ic_logger::init().unwrap();
+/// for _ in 0..3 {
+///     ic_logger::warn_once!("legacy-api", "the legacy API is deprecated");
+/// }
+/// ```
+///
+/// Does nothing if the logger was not initialised yet.
+pub fn log_once(key: &str, target: &str, level: Level, args: fmt::Arguments) {
+    let first = with_logger(|logger| {
+        logger
+            .once_keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key.to_string())
+    });
+    if first == Some(true) {
+        log_to(target, level, args);
+    }
+}
+
+/// Capture the lines logged while running `f` instead of printing them.
+///
+/// This is meant for testing a specific code path. The lines still end up in the buffer and are
+/// forwarded as usual. When captures are nested, the lines of the inner one are also part of the
+/// outer one.
+///
+/// ```no_run
+/// ic_logger::init().unwrap();
+///
+/// let lines = ic_logger::capture(|| log::warn!("low balance"));
+/// assert_eq!(lines.len(), 1);
+/// ```
+pub fn capture(f: impl FnOnce()) -> Vec<String> {
+    capture_levels(f).into_iter().map(|(_level, line)| line).collect()
+}
+
+/// Capture the lines logged while running `f` along with the level of their record, see [`capture`].
+pub(crate) fn capture_levels(f: impl FnOnce()) -> Vec<(Level, String)> {
+    let outer = with_logger(|logger| logger.captured().replace(Vec::new())).flatten();
+    f();
+    let captured = with_logger(|logger| std::mem::replace(&mut *logger.captured(), outer))
+        .flatten()
+        .unwrap_or_default();
+
+    with_logger(|logger| {
+        if let Some(outer) = logger.captured().as_mut() {
+            outer.extend(captured.iter().cloned());
+        }
+    });
+    captured
+}
+
+/// Print all lines held back by [`IcLogger::with_batching`].
+///
+/// Meant to be called from the canister's heartbeat, so that lines are printed once per heartbeat.
+pub fn flush_on_heartbeat() {
+    with_logger(|logger| logger.flush());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ic_cdk::api::call::RejectionCode;
+
+    #[test]
+    fn test_verbose_quiet() {
+        let level = |logger: IcLogger| logger.filter.default_level();
+
+        assert_eq!(level(IcLogger::new().quiet()), LevelFilter::Error);
+        assert_eq!(level(IcLogger::new().quiet().quiet().quiet()), LevelFilter::Off);
+        assert_eq!(level(IcLogger::new().verbose().verbose()), LevelFilter::Debug);
+        assert_eq!(
+            level(IcLogger::new().verbose().verbose().verbose().verbose()),
+            LevelFilter::Trace
+        );
+        assert_eq!(level(IcLogger::new().verbose().quiet()), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn test_level_index() {
+        let level = |index| IcLogger::new().with_level_index(index).filter.default_level();
+
+        assert_eq!(level(0), LevelFilter::Off);
+        assert_eq!(level(2), LevelFilter::Warn);
+        assert_eq!(level(5), LevelFilter::Trace);
+        assert_eq!(level(6), LevelFilter::Trace);
+        assert_eq!(level(usize::MAX), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_module_level_fn() {
+        thread_local! {
+            static LEVEL: Cell<LevelFilter> = const { Cell::new(LevelFilter::Warn) };
+        }
+        let mut logger = IcLogger::new()
+            .with_level(LevelFilter::Error)
+            .with_module_level("my_crate::payments", LevelFilter::Off)
+            .with_module_level_fn("my_crate::payments", || LEVEL.get());
+        let id = logger.filter.intern_target("my_crate::payments::ledger");
+
+        assert!(logger.enabled(&create_log("my_crate::payments::ledger", Level::Warn)));
+        assert!(!logger.enabled(&create_log("my_crate::payments", Level::Debug)));
+        assert!(!logger.enabled(&create_log("my_crate", Level::Warn)));
+        assert!(!logger.enabled_id(id, Level::Debug));
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+
+        LEVEL.set(LevelFilter::Debug);
+        assert!(logger.enabled(&create_log("my_crate::payments", Level::Debug)));
+        assert!(logger.enabled_id(id, Level::Debug));
+
+        LEVEL.set(LevelFilter::Off);
+        assert!(!logger.enabled(&create_log("my_crate::payments", Level::Error)));
+
+        let mut logger = IcLogger::new()
+            .with_module_level_fn("my_crate", || LevelFilter::Off)
+            .with_module_level("my_crate::payments", LevelFilter::Info)
+            .with_module_level_fn("my_crate::payments::ledger", || LevelFilter::Trace)
+            .with_module_level_fn("my_crate::payments::ledger", || LevelFilter::Debug);
+        let id = logger.filter.intern_target("my_crate::payments::fees");
+
+        assert!(logger.enabled(&create_log("my_crate::payments::fees", Level::Info)));
+        assert!(logger.enabled_id(id, Level::Info));
+        assert!(!logger.enabled(&create_log("my_crate::db", Level::Error)));
+        assert!(logger.enabled(&create_log("my_crate::payments::ledger", Level::Debug)));
+        assert!(!logger.enabled(&create_log("my_crate::payments::ledger", Level::Trace)));
+    }
+
+    #[test]
+    fn test_module_levels_allowlist() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Off)
+            .with_module_level("my_crate", LevelFilter::Info);
+
+        assert!(logger.enabled(&create_log("my_crate", Level::Info)));
+        assert!(logger.enabled(&create_log("my_crate::module", Level::Info)));
+        assert!(!logger.enabled(&create_log("my_crate::module", Level::Debug)));
+        assert!(!logger.enabled(&create_log("not_my_crate", Level::Debug)));
+        assert!(!logger.enabled(&create_log("not_my_crate::module", Level::Error)));
+    }
+
+    #[test]
+    fn test_module_levels_denylist() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Debug)
+            .with_module_level("my_crate", LevelFilter::Trace)
+            .with_module_level("chatty_dependency", LevelFilter::Info);
+
+        assert!(logger.enabled(&create_log("my_crate", Level::Info)));
+        assert!(logger.enabled(&create_log("my_crate", Level::Trace)));
+        assert!(logger.enabled(&create_log("my_crate::module", Level::Info)));
+        assert!(logger.enabled(&create_log("my_crate::module", Level::Trace)));
+        assert!(logger.enabled(&create_log("not_my_crate", Level::Debug)));
+        assert!(!logger.enabled(&create_log("not_my_crate::module", Level::Trace)));
+        assert!(logger.enabled(&create_log("chatty_dependency", Level::Info)));
+        assert!(!logger.enabled(&create_log("chatty_dependency", Level::Debug)));
+        assert!(!logger.enabled(&create_log("chatty_dependency::module", Level::Debug)));
+        assert!(logger.enabled(&create_log("chatty_dependency::module", Level::Warn)));
+    }
+
+    #[test]
+    fn test_level_table() {
+        for filter in LevelFilter::iter() {
+            let logger = IcLogger::new().with_level(filter);
+            for level in Level::iter() {
+                let expected = level <= filter && level <= log::STATIC_MAX_LEVEL;
+                assert_eq!(logger.enabled(&create_log("my_crate", level)), expected);
+
+                let lines = capture_with(&logger, || {
+                    logger.log(&create_record("my_crate", level, format_args!("message")))
+                });
+                assert_eq!(lines.len(), usize::from(expected), "{level} against {filter}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_fn() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Off)
+            .with_module_level("my_crate", LevelFilter::Debug)
+            .with_filter_fn(|metadata| !metadata.target().ends_with("::api") || metadata.level() <= Level::Info);
+
+        assert!(logger.enabled(&create_log("my_crate::db", Level::Debug)));
+        assert!(logger.enabled(&create_log("my_crate::api", Level::Info)));
+        assert!(!logger.enabled(&create_log("my_crate::api", Level::Debug)));
+        assert!(!logger.enabled(&create_log("other_crate", Level::Error)));
+
+        let mut logger = logger;
+        let id = logger.intern_target("my_crate::api");
+        assert!(logger.enabled_id(id, Level::Info));
+        assert!(!logger.enabled_id(id, Level::Debug));
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_module_level("my_crate", LevelFilter::Off);
+
+        assert!(!logger.enabled(&create_log("my_crate", Level::Error)));
+
+        let logger = logger.with_passthrough(LevelFilter::Error);
+        assert!(logger.enabled(&create_log("my_crate", Level::Error)));
+        assert!(logger.enabled(&create_log("my_crate::module", Level::Error)));
+        assert!(!logger.enabled(&create_log("my_crate", Level::Warn)));
+        assert!(logger.enabled(&create_log("other_crate", Level::Info)));
+        assert!(!logger.enabled(&create_log("other_crate", Level::Debug)));
+
+        let logger = logger.with_passthrough(LevelFilter::Off);
+        assert!(!logger.enabled(&create_log("my_crate", Level::Error)));
+    }
+
+    #[test]
+    fn test_always_log() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Warn)
+            .with_module_level("alerts", LevelFilter::Off)
+            .with_always_log("alerts");
+
+        assert!(logger.enabled(&create_log("alerts", Level::Trace)));
+        assert!(logger.enabled(&create_log("alerts::cycles", Level::Debug)));
+        assert!(!logger.enabled(&create_log("my_crate", Level::Debug)));
+    }
+
+    #[test]
+    fn test_require_kv() {
+        let logger = IcLogger::new().with_require_kv("audit");
+        let key_values = [("user", "alice")];
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("audit::login", Level::Warn, format_args!("plain")));
+            logger.log(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("audit::login")
+                    .args(format_args!("structured"))
+                    .key_values(&key_values)
+                    .build(),
+            );
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("plain")));
+        });
+        assert_eq!(
+            lines,
+            ["[WARN  audit::login] structured user=alice", "[WARN  my_crate] plain"]
+        );
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_render() {
+        let logger = IcLogger::new()
+            .with_module_level("my_crate", LevelFilter::Info)
+            .with_timestamps(true)
+            .with_clock(|| 1_690_499_289_718_590_904)
+            .with_logical_clock(true)
+            .with_line_interceptor(|line| Some(line.replace("secret", "******")));
+        let records = [
+            create_record("my_crate", Level::Info, format_args!("first secret")),
+            create_record("my_crate", Level::Debug, format_args!("filtered")),
+            create_record("force:other_crate", Level::Info, format_args!("forced")),
+            create_record("my_crate", Level::Warn, format_args!("second")),
+        ];
+
+        let mut rendered = Vec::new();
+        let lines = capture_with(&logger, || {
+            for record in &records {
+                rendered.extend(logger.render(record));
+                logger.log(record);
+            }
+        });
+        assert_eq!(rendered, lines);
+        assert_eq!(
+            rendered[0],
+            "[2023-07-27T23:08:09.718590904Z INFO  my_crate] first ****** seq=0"
+        );
+        assert_eq!(rendered.len(), 3);
+    }
+
+    #[test]
+    fn test_force_prefix() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_module_level("my_crate::db", LevelFilter::Warn);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate::db", Level::Info, format_args!("slow query")));
+            logger.log(&create_record(
+                "force:my_crate::db",
+                Level::Info,
+                format_args!("slow query"),
+            ));
+        });
+        assert_eq!(lines, ["[INFO  my_crate::db] slow query"]);
+        assert!(logger.enabled(&create_log("force:my_crate::db", Level::Trace)));
+    }
+
+    #[test]
+    fn test_rate_limited_count() {
+        let logger = IcLogger::new()
+            .with_rate_limit(2, Duration::from_secs(1))
+            .with_clock(|| 1_000_000_000);
+
+        let lines = capture_with(&logger, || {
+            for message in ["first", "second", "third"] {
+                logger.log(&create_record("my_crate", Level::Warn, format_args!("{message}")));
+            }
+            logger.log(&create_record("my_crate", Level::Debug, format_args!("filtered")));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] first", "[WARN  my_crate] second"]);
+        assert_eq!(logger.rate_limited_count(), 1);
+        assert_eq!(IcLogger::new().rate_limited_count(), 0);
+        assert_eq!(rate_limited_count(), 0);
+    }
+
+    #[test]
+    fn test_default_clock() {
+        let logger = IcLogger::new().with_rate_limit(1, Duration::from_secs(60));
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("first")));
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("second")));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] first"]);
+        assert!(time() > 1_690_499_289_718_590_904);
+    }
+
+    #[test]
+    fn test_suppressed_count() {
+        let logger = IcLogger::new().with_level(LevelFilter::Info);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Debug, format_args!("details")));
+            logger.log(&create_record("my_crate", Level::Info, format_args!("started")));
+            logger.log(&create_record("my_crate", Level::Trace, format_args!("noise")));
+        });
+        assert_eq!(lines, ["[INFO  my_crate] started"]);
+        assert_eq!(logger.suppressed_count(), 2);
+
+        assert_eq!(suppressed_count(), 0);
+        IcLogger::new().init().unwrap();
+        log::logger().log(&create_record("my_crate", Level::Info, format_args!("details")));
+        assert_eq!(suppressed_count(), 1);
+    }
+
+    #[test]
+    fn test_output_budget() {
+        let logger = IcLogger::new().with_output_budget(40);
+
+        let lines = capture_with(&logger, || {
+            for message in ["first", "second", "third", "fourth"] {
+                logger.log(&create_record("my_crate", Level::Warn, format_args!("{message}")));
+            }
+        });
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] first",
+                "[WARN  my_crate] second",
+                "[WARN  ic_logger] output budget of 40 bytes exhausted, logging stopped"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_logical_clock() {
+        let record = || create_record("my_crate", Level::Warn, format_args!("step"));
+        let logger = IcLogger::new().with_logical_clock(true).with_clock(|| 42);
+
+        let lines = capture_with(&logger, || (0..3).for_each(|_| logger.log(&record())));
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] step seq=0",
+                "[WARN  my_crate] step seq=1",
+                "[WARN  my_crate] step seq=2"
+            ]
+        );
+
+        let logger = IcLogger::new()
+            .with_logical_clock_reset(SequenceReset::OnTimeChange)
+            .with_logical_clock(true);
+        assert_eq!(logger.sequence.as_ref().unwrap().reset(), SequenceReset::OnTimeChange);
+        assert!(IcLogger::new().with_logical_clock(false).sequence.is_none());
+    }
+
+    #[test]
+    fn test_short_target() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Off)
+            .with_module_level("my_crate::storage", LevelFilter::Info)
+            .with_module_level("flat", LevelFilter::Info)
+            .with_short_target(true);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record(
+                "my_crate::storage::db",
+                Level::Info,
+                format_args!("nested"),
+            ));
+            logger.log(&create_record("flat", Level::Info, format_args!("flat")));
+            logger.log(&create_record("other_crate::db", Level::Info, format_args!("filtered")));
+        });
+        assert_eq!(lines, ["[INFO  db] nested", "[INFO  flat] flat"]);
+    }
+
+    #[test]
+    fn test_crate_only() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Trace)
+            .with_crate_only("my_crate", LevelFilter::Debug);
+
+        assert!(logger.enabled(&create_log("my_crate", Level::Debug)));
+        assert!(logger.enabled(&create_log("my_crate::module", Level::Debug)));
+        assert!(!logger.enabled(&create_log("my_crate::module", Level::Trace)));
+        assert!(!logger.enabled(&create_log("dependency", Level::Error)));
+    }
+
+    #[test]
+    fn test_version() {
+        let logger = IcLogger::new().with_version("1.2.3");
+
+        assert_eq!(
+            logger.format_record(&create_record("my_crate", Level::Warn, format_args!("first"))),
+            "[1.2.3 WARN  my_crate] first"
+        );
+        assert_eq!(
+            logger.format_record(&create_record("my_crate::module", Level::Info, format_args!("second"))),
+            "[1.2.3 INFO  my_crate::module] second"
+        );
+        assert_eq!(
+            IcLogger::new().format_record(&create_record("my_crate", Level::Warn, format_args!("first"))),
+            "[WARN  my_crate] first"
+        );
+        assert_eq!(
+            logger
+                .with_format(Format::Json)
+                .with_tag("payments")
+                .format_record(&create_record("my_crate", Level::Warn, format_args!("first"))),
+            r#"{"v":2,"version":"1.2.3","tag":"payments","level":"WARN","target":"my_crate","msg":"first"}"#
+        );
+    }
+
+    #[test]
+    fn test_kv_align() {
+        let key_values = [("id", "7"), ("status", "failed")];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("my_crate")
+            .args(format_args!("request done"))
+            .key_values(&key_values)
+            .build();
+
+        assert_eq!(
+            IcLogger::new().format_record(&record),
+            "[WARN  my_crate] request done id=7 status=failed"
+        );
+        assert_eq!(
+            IcLogger::new().with_kv_align(true).format_record(&record),
+            "[WARN  my_crate] request done id    =7 status=failed"
+        );
+    }
+
+    #[test]
+    fn test_quick() {
+        assert!(!would_log("my_crate", Level::Error));
+
+        quick(LevelFilter::Warn, &[("my_crate", LevelFilter::Debug)]).unwrap();
+
+        assert!(would_log("my_crate", Level::Debug));
+        assert!(would_log("my_crate::module", Level::Debug));
+        assert!(!would_log("my_crate::module", Level::Trace));
+        assert!(would_log("not_my_crate", Level::Warn));
+        assert!(!would_log("not_my_crate", Level::Info));
+        assert!(quick(LevelFilter::Trace, &[]).is_err());
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_timestamps() {
+        let logger = IcLogger::new()
+            .with_timestamps(true)
+            .with_clock(|| 1_690_499_289_718_590_904);
+
+        assert_eq!(
+            logger.format_record(&create_record("my_crate", Level::Warn, format_args!("message"))),
+            "[2023-07-27T23:08:09.718590904Z WARN  my_crate] message"
+        );
+    }
+
+    #[cfg(not(feature = "timestamps"))]
+    #[test]
+    fn test_timestamps_disabled() {
+        let logger = IcLogger::new()
+            .with_timestamps(true)
+            .with_target_timestamp("my_crate")
+            .with_timestamp_format(TimestampFormat::SinceInit)
+            .with_clock(|| panic!("the time is read"));
+
+        assert_eq!(
+            logger.format_record(&create_record("my_crate", Level::Warn, format_args!("message"))),
+            "[WARN  my_crate] message"
+        );
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_target_timestamp() {
+        let logger = IcLogger::new()
+            .with_target_timestamp("my_crate::perf")
+            .with_clock(|| 1_690_499_289_718_590_904);
+
+        assert_eq!(
+            logger.format_record(&create_record("my_crate::perf::db", Level::Warn, format_args!("slow"))),
+            "[2023-07-27T23:08:09.718590904Z WARN  my_crate::perf::db] slow"
+        );
+        assert_eq!(
+            logger.format_record(&create_record("my_crate::api", Level::Warn, format_args!("fast"))),
+            "[WARN  my_crate::api] fast"
+        );
+    }
+
+    #[test]
+    fn test_message_hash() {
+        let logger = IcLogger::new().with_message_hash(true);
+        let first = logger.format_record(&create_record("my_crate", Level::Warn, format_args!("disk full")));
+        let repeated = logger.format_record(&create_record("other_crate", Level::Error, format_args!("disk full")));
+        let different = logger.format_record(&create_record("my_crate", Level::Warn, format_args!("disk empty")));
+
+        assert_eq!(first, "[WARN  my_crate] disk full #a9055c11");
+        assert!(repeated.ends_with(" #a9055c11"));
+        assert!(!different.ends_with(" #a9055c11"));
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_column_order() {
+        let logger = IcLogger::new()
+            .with_tag("payments")
+            .with_timestamps(true)
+            .with_clock(|| 0);
+        let record = create_record("my_crate", Level::Warn, format_args!("message"));
+
+        assert_eq!(
+            logger.format_record(&record),
+            "[1970-01-01T00:00:00.000000000Z payments WARN  my_crate] message"
+        );
+
+        let logger = logger.with_column_order(&[Column::Level, Column::Tag, Column::Message, Column::Timestamp]);
+        assert_eq!(
+            logger.format_record(&record),
+            "[WARN  payments] message [1970-01-01T00:00:00.000000000Z]"
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        thread_local! {
+            static PRINTED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        }
+        let mut logger = IcLogger::new()
+            .with_format(Format::Csv)
+            .with_csv_header(true)
+            .with_buffer(10);
+        logger.print = |line| PRINTED.with_borrow_mut(|printed| printed.push(line.to_string()));
+
+        assert_eq!(
+            logger.format_record(&create_record("my_crate", Level::Warn, format_args!("a, \"b\""))),
+            "WARN,my_crate,\"a, \"\"b\"\"\""
+        );
+        assert!(logger.init().is_ok());
+        log_to("my_crate", Level::Warn, format_args!("first"));
+        log_to("my_crate", Level::Warn, format_args!("second"));
+        assert_eq!(
+            PRINTED.take(),
+            [CSV_HEADER, "WARN,my_crate,first", "WARN,my_crate,second"]
+        );
+        assert_eq!(tail(10, Level::Trace), ["WARN,my_crate,first", "WARN,my_crate,second"]);
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_presets() {
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("my_canister")
+            .file(Some("src/lib.rs"))
+            .line(Some(42))
+            .args(format_args!("message"))
+            .build();
+        let logger = |preset| {
+            IcLogger::new()
+                .with_version("1.2.3")
+                .with_preset(preset)
+                .with_clock(|| 1_690_499_289_718_590_904)
+        };
+
+        assert_eq!(
+            logger(Preset::Compact).format_record(&record),
+            "[WARN  my_canister] message"
+        );
+        assert_eq!(
+            logger(Preset::Verbose).format_record(&record),
+            "[2023-07-27T23:08:09.718590904Z 1.2.3 WARN  my_canister src/lib.rs:42] message"
+        );
+        assert_eq!(
+            logger(Preset::Json).format_record(&record),
+            r#"{"v":2,"ts":1690499289718590904,"version":"1.2.3","level":"WARN","target":"my_canister","msg":"message"}"#
+        );
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_field_order() {
+        let record = create_record("my_crate", Level::Warn, format_args!("message"));
+        let logger = || IcLogger::new().with_timestamps(true).with_clock(|| 0);
+
+        assert_eq!(
+            logger()
+                .with_field_order(FieldOrder::TimestampLevelTarget)
+                .format_record(&record),
+            "[1970-01-01T00:00:00.000000000Z WARN  my_crate] message"
+        );
+        assert_eq!(
+            logger()
+                .with_field_order(FieldOrder::LevelTimestampTarget)
+                .format_record(&record),
+            "[WARN  1970-01-01T00:00:00.000000000Z my_crate] message"
+        );
+        assert_eq!(
+            logger()
+                .with_field_order(FieldOrder::LevelTargetTimestamp)
+                .format_record(&record),
+            "[WARN  my_crate 1970-01-01T00:00:00.000000000Z] message"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Column::Message")]
+    fn test_column_order_without_message() {
+        let _ = IcLogger::new().with_column_order(&[Column::Level, Column::Target]);
+    }
+
+    #[test]
+    fn test_raw_print_backend() {
+        thread_local! {
+            static PRINTED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        }
+        let logger = |backend| {
+            let mut logger = IcLogger::new().with_backend(backend);
+            logger.print = |line| PRINTED.with_borrow_mut(|printed| printed.push(line.to_string()));
+            logger
+        };
+        let record = create_record("my_crate", Level::Warn, format_args!("message"));
+
+        logger(Backend::Println).log(&record);
+        logger(Backend::RawPrint).log(&record);
+        assert_eq!(PRINTED.take(), ["[WARN  my_crate] message"; 2]);
+    }
+
+    #[test]
+    fn test_update_only() {
+        let mut logger = IcLogger::new().with_update_only(true);
+
+        logger.in_query = || false;
+        assert!(logger.enabled(&create_log("my_crate", Level::Warn)));
+        logger.in_query = || true;
+        assert!(!logger.enabled(&create_log("my_crate", Level::Error)));
+        assert!(!in_query_call());
+
+        let mut logger = IcLogger::new();
+        logger.in_query = || true;
+        assert!(logger.enabled(&create_log("my_crate", Level::Warn)));
+    }
+
+    #[test]
+    fn test_quiet_window() {
+        const HOUR: u64 = 3_600_000_000_000;
+
+        let logger = |clock| {
+            IcLogger::new()
+                .with_level(LevelFilter::Debug)
+                .with_quiet_window(22 * HOUR, 2 * HOUR, LevelFilter::Warn)
+                .with_clock(clock)
+        };
+
+        let outside = logger(|| 3 * NANOS_PER_DAY + 12 * HOUR);
+        assert!(outside.enabled(&create_log("my_crate", Level::Debug)));
+        assert!(!outside.enabled(&create_log("my_crate", Level::Trace)));
+
+        for inside in [
+            logger(|| 3 * NANOS_PER_DAY + 23 * HOUR),
+            logger(|| 3 * NANOS_PER_DAY + HOUR),
+        ] {
+            assert!(inside.enabled(&create_log("my_crate", Level::Warn)));
+            assert!(!inside.enabled(&create_log("my_crate", Level::Info)));
+            assert!(!inside.enabled(&create_log("my_crate", Level::Debug)));
+        }
+
+        let edge = logger(|| 2 * HOUR);
+        assert!(edge.enabled(&create_log("my_crate", Level::Debug)));
+    }
+
+    #[test]
+    fn test_intern_target() {
+        let targets = ["other_crate", "my_crate", "my_crate::db", "audit"];
+        let mut logger = IcLogger::new()
+            .with_module_level("my_crate", LevelFilter::Info)
+            .with_module_level("my_crate::db", LevelFilter::Off)
+            .with_passthrough(LevelFilter::Error)
+            .with_always_log("audit");
+        let ids: Vec<_> = targets.iter().map(|target| logger.intern_target(target)).collect();
+
+        for (target, id) in targets.iter().zip(ids) {
+            for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+                assert_eq!(logger.enabled_id(id, level), logger.enabled(&create_log(target, level)));
+            }
+        }
+
+        assert_eq!(intern_target("my_crate"), None);
+        IcLogger::new().init().unwrap();
+        let id = intern_target("my_crate").unwrap();
+        assert!(would_log_id(id, Level::Warn));
+        assert!(!would_log_id(id, Level::Info));
+
+        ensure_installed(IcLogger::new().with_module_level("my_crate", LevelFilter::Info)).unwrap();
+        assert!(would_log_id(id, Level::Info));
+        assert_eq!(intern_target("my_crate"), Some(id));
+    }
+
+    #[test]
+    fn test_describe() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_module_level("my_crate::db", LevelFilter::Trace)
+            .with_buffer(100);
+        let description = logger.describe();
+
+        assert!(description.starts_with("default level: INFO\n"));
+        assert!(description.contains("\n  my_crate::db => TRACE\n  <default> => INFO\n"));
+        assert!(description.contains("\nbuffer: 100 lines"));
+        assert!(!description.contains("timestamps"));
+
+        assert_eq!(super::describe(), None);
+        IcLogger::new().init().unwrap();
+        assert!(super::describe().unwrap().starts_with("default level: WARN\n"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let framework = IcLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_module_level("framework", LevelFilter::Debug)
+            .with_module_level("my_crate", LevelFilter::Warn);
+        let app = IcLogger::new()
+            .with_level(LevelFilter::Error)
+            .with_module_level("my_crate", LevelFilter::Trace)
+            .with_module_level("my_crate::db", LevelFilter::Off);
+
+        let merged = framework.merge(app);
+        assert_eq!(
+            merged.resolved_rules(),
+            [
+                ("my_crate::db".to_string(), LevelFilter::Off),
+                ("framework".to_string(), LevelFilter::Debug),
+                ("my_crate".to_string(), LevelFilter::Trace),
+                (String::new(), LevelFilter::Info),
+            ]
+        );
+        assert!(merged.enabled(&create_log("my_crate::api", Level::Trace)));
+        assert!(!merged.enabled(&create_log("my_crate::db", Level::Error)));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let targets = ["other_crate", "my_crate", "my_crate::db"];
+        let levels = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+        let enabled = |logger: &IcLogger| {
+            targets
+                .iter()
+                .flat_map(|target| levels.map(|level| logger.enabled(&create_log(target, level))))
+                .collect::<Vec<_>>()
+        };
+
+        let logger = IcLogger::new().with_module_level("my_crate", LevelFilter::Info);
+        let original = enabled(&logger);
+        let snapshot = logger.snapshot();
+
+        let mut logger = logger
+            .with_level(LevelFilter::Off)
+            .with_module_level("my_crate::db", LevelFilter::Trace);
+        assert_ne!(enabled(&logger), original);
+        logger.restore(snapshot);
+        assert_eq!(enabled(&logger), original);
+
+        IcLogger::new().init().unwrap();
+        let installed = super::snapshot().unwrap();
+        with_logger(|logger| assert_eq!(logger.snapshot(), installed));
+        restore(IcLogger::new().with_level(LevelFilter::Debug).snapshot());
+        assert!(would_log("my_crate", Level::Debug));
+        restore(installed);
+        assert!(!would_log("my_crate", Level::Debug));
+    }
+
+    #[test]
+    fn test_cycles_gate() {
+        let mut logger = IcLogger::new().with_cycles_gate(1_000_000);
+
+        logger.balance = || 1_000_000;
+        assert!(logger.enabled(&create_log("my_crate", Level::Warn)));
+        logger.balance = || 999_999;
+        assert!(!logger.enabled(&create_log("my_crate", Level::Error)));
+        assert_eq!(canister_balance(), u128::MAX);
+
+        let mut logger = IcLogger::new();
+        logger.balance = || 0;
+        assert!(logger.enabled(&create_log("my_crate", Level::Warn)));
+    }
+
+    #[test]
+    fn test_static_max_level() {
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Trace)
+            .with_passthrough(LevelFilter::Trace)
+            .with_always_log("my_crate");
+
+        for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+            assert_eq!(
+                logger.enabled(&create_log("my_crate", level)),
+                level <= log::STATIC_MAX_LEVEL
+            );
+        }
+        assert!(logger.max_level() <= log::STATIC_MAX_LEVEL);
+    }
+
+    #[test]
+    fn test_is_initialized() {
+        assert!(!is_initialized());
+        assert!(IcLogger::new().with_directives("my_crate=loud").init().is_err());
+        assert!(!is_initialized());
+        IcLogger::new().with_level(LevelFilter::Info).init().unwrap();
+        assert!(is_initialized());
+        assert!(IcLogger::new().init().is_err());
+        assert!(is_initialized());
+        assert!(!std::thread::spawn(is_initialized).join().unwrap());
+    }
+
+    #[test]
+    fn test_ensure_installed() {
+        ensure_installed(IcLogger::new().with_level(LevelFilter::Info)).unwrap();
+        assert!(would_log("my_crate", Level::Info));
+        assert!(!would_log("my_crate", Level::Debug));
+
+        ensure_installed(IcLogger::new().with_level(LevelFilter::Debug).with_buffer(10)).unwrap();
+        assert!(would_log("my_crate", Level::Debug));
+        log_to("my_crate", Level::Debug, format_args!("reconfigured"));
+        assert_eq!(tail(1, Level::Debug), ["[DEBUG my_crate] reconfigured"]);
+
+        ensure_installed(
+            IcLogger::new()
+                .with_directives("my_crate=loud")
+                .with_lenient_parsing(LevelFilter::Info)
+                .with_buffer(10),
+        )
+        .unwrap();
+        assert_eq!(
+            tail(10, Level::Trace),
+            ["[WARN  ic_logger] unknown level in `my_crate=loud`, using INFO instead"]
+        );
+    }
+
+    #[test]
+    fn test_floor_level() {
+        IcLogger::new()
+            .with_module_level("my_crate::db", LevelFilter::Off)
+            .init()
+            .unwrap();
+        assert!(!would_log("my_crate::db", Level::Error));
+        assert!(!would_log("other_crate", Level::Info));
+
+        set_floor_level(LevelFilter::Debug);
+        assert!(would_log("my_crate::db", Level::Debug));
+        assert!(!would_log("my_crate::db", Level::Trace));
+        assert!(would_log("other_crate", Level::Info));
+
+        set_floor_level(LevelFilter::Off);
+        assert!(!would_log("my_crate::db", Level::Error));
+    }
+
+    #[test]
+    fn test_set_suppressed() {
+        IcLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_passthrough(LevelFilter::Error)
+            .with_buffer(10)
+            .init()
+            .unwrap();
+
+        set_suppressed(true);
+        for level in Level::iter() {
+            log_to("my_crate", level, format_args!("suppressed"));
+            log_to("force:my_crate", level, format_args!("suppressed"));
+        }
+        assert!(!would_log("my_crate", Level::Error));
+        assert!(get_logs().is_empty());
+
+        set_suppressed(false);
+        log_to("my_crate", Level::Error, format_args!("logged"));
+        assert_eq!(tail(10, Level::Trace), ["[ERROR my_crate] logged"]);
+    }
+
+    #[test]
+    fn test_init_error() {
+        IcLogger::new().init().unwrap();
+        assert!(matches!(IcLogger::new().init(), Err(Error::AlreadyInitialized)));
+
+        let error = Error::from(log::set_logger(&INSTALLED_LOGGER).unwrap_err());
+        assert!(matches!(error, Error::LoggerAlreadySet(_)));
+        assert!(std::error::Error::source(&error).is_some());
+        assert!(std::error::Error::source(&Error::AlreadyInitialized).is_none());
+    }
+
+    #[test]
+    fn test_would_buffer() {
+        let logger = IcLogger::new().with_console_and_buffer(LevelFilter::Warn, LevelFilter::Debug);
+
+        assert!(logger.would_buffer(&create_log("my_crate", Level::Debug)));
+        assert!(!logger.would_buffer(&create_log("my_crate", Level::Trace)));
+        assert!(logger.enabled(&create_log("my_crate", Level::Debug)));
+
+        let logger = IcLogger::new().with_console_and_buffer(LevelFilter::Debug, LevelFilter::Warn);
+        assert!(logger.enabled(&create_log("my_crate", Level::Info)));
+        assert!(!logger.would_buffer(&create_log("my_crate", Level::Info)));
+        assert!(logger.would_buffer(&create_log("my_crate", Level::Error)));
+
+        assert!(!IcLogger::new().would_buffer(&create_log("my_crate", Level::Error)));
+        assert!(!would_buffer(&create_log("my_crate", Level::Error)));
+    }
+
+    #[test]
+    fn test_max_rules() {
+        let config = |rules: usize| {
+            (0..rules).fold(IcLogger::new().with_max_rules(2), |logger, index| {
+                logger.with_module_level(&format!("my_crate::request_{index}"), LevelFilter::Debug)
+            })
+        };
+
+        let error = config(3).init().unwrap_err();
+        assert!(matches!(error, Error::TooManyRules { count: 3, max: 2 }));
+        assert_eq!(error.to_string(), "3 level rules exceed the maximum of 2");
+        assert!(!is_initialized());
+        assert!(matches!(
+            config(1)
+                .with_module_level_fn("other", || LevelFilter::Info)
+                .with_module_level("a", LevelFilter::Info)
+                .init(),
+            Err(Error::TooManyRules { count: 3, max: 2 })
+        ));
+
+        config(2).init().unwrap();
+        assert!(matches!(ensure_installed(config(3)), Err(Error::TooManyRules { .. })));
+        assert!(would_log("my_crate::request_1", Level::Debug));
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let logger = IcLogger::new().with_directives("warn, my_crate=INFO ,my_crate::db=verbose");
+        assert!(logger.parse_warnings().is_empty());
+        assert!(matches!(logger.init(), Err(Error::InvalidLevel(token)) if token == "verbose"));
+
+        let logger = IcLogger::new()
+            .with_directives("warn,my_crate=info,my_crate::db=verbose")
+            .with_lenient_parsing(LevelFilter::Debug);
+        assert_eq!(
+            logger.resolved_rules(),
+            [
+                ("my_crate::db".to_string(), LevelFilter::Debug),
+                ("my_crate".to_string(), LevelFilter::Info),
+                (String::new(), LevelFilter::Warn),
+            ]
+        );
+        assert_eq!(
+            logger.parse_warnings(),
+            ["unknown level in `my_crate::db=verbose`, using DEBUG instead"]
+        );
+
+        let logger = IcLogger::new()
+            .with_lenient_parsing(LevelFilter::Error)
+            .with_directives("loud");
+        assert_eq!(logger.filter.default_level(), LevelFilter::Error);
+        assert_eq!(
+            logger.parse_warnings(),
+            ["unknown level in `loud`, using ERROR instead"]
+        );
+    }
+
+    #[test]
+    fn test_console_and_buffer() {
+        let logger = IcLogger::new().with_console_and_buffer(LevelFilter::Warn, LevelFilter::Debug);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Debug, format_args!("details")));
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("problem")));
+            logger.log(&create_record("my_crate", Level::Trace, format_args!("noise")));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] problem"]);
+
+        let buffered: Vec<_> = logger
+            .buffer()
+            .unwrap()
+            .entries()
+            .map(|entry| entry.line.clone())
+            .collect();
+        assert_eq!(buffered, ["[DEBUG my_crate] details", "[WARN  my_crate] problem"]);
+    }
+
+    #[test]
+    fn test_export_chunks() {
+        assert!(export_chunks(100).is_empty());
+
+        IcLogger::new().with_buffer(3).init().unwrap();
+        for message in ["first", "second", "third", "fourth"] {
+            log::logger().log(&create_record("my_crate", Level::Warn, format_args!("{message}")));
+        }
+
+        let lines: Vec<_> = get_logs().into_iter().map(|entry| entry.line).collect();
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] second",
+                "[WARN  my_crate] third",
+                "[WARN  my_crate] fourth"
+            ]
+        );
+        assert_eq!(
+            export_chunks(46),
+            [
+                "[WARN  my_crate] second\n[WARN  my_crate] third",
+                "[WARN  my_crate] fourth"
+            ]
+        );
+        assert_eq!(export_chunks(45).len(), 3);
+    }
+
+    #[test]
+    fn test_log_to() {
+        IcLogger::new()
+            .with_module_level("payments", LevelFilter::Info)
+            .with_buffer(10)
+            .init()
+            .unwrap();
+
+        log_to("payments", Level::Info, format_args!("charged {} cycles", 100));
+        log_to("payments", Level::Debug, format_args!("balance checked"));
+        log_to("other", Level::Info, format_args!("ignored"));
+
+        let entries = get_logs();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].target, "payments");
+        assert_eq!(entries[0].line, "[INFO  payments] charged 100 cycles");
+    }
+
+    #[test]
+    fn test_log_once() {
+        IcLogger::new().with_buffer(10).init().unwrap();
+
+        for attempt in 0..3 {
+            log_once(
+                "legacy-api",
+                "my_crate",
+                Level::Warn,
+                format_args!("deprecated {attempt}"),
+            );
+        }
+        crate::warn_once!("other-key", "from a macro");
+        crate::warn_once!("other-key", "from a macro");
+
+        let lines: Vec<_> = get_logs().into_iter().map(|entry| entry.line).collect();
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] deprecated 0",
+                "[WARN  ic_logger::logger::test] from a macro"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        IcLogger::new().init().unwrap();
+        let check_balance = |balance: u64| {
+            if balance < 10 {
+                log_to("my_crate", Level::Warn, format_args!("low balance: {balance}"));
+            }
+        };
+
+        assert_eq!(capture(|| check_balance(5)), ["[WARN  my_crate] low balance: 5"]);
+        assert!(capture(|| check_balance(50)).is_empty());
+
+        let outer = capture(|| {
+            check_balance(1);
+            assert_eq!(capture(|| check_balance(2)), ["[WARN  my_crate] low balance: 2"]);
+        });
+        assert_eq!(
+            outer,
+            ["[WARN  my_crate] low balance: 1", "[WARN  my_crate] low balance: 2"]
+        );
+        assert!(with_logger(|logger| logger.captured().is_none()).unwrap());
+    }
+
+    #[test]
+    fn test_drain() {
+        assert!(drain().is_empty());
+
+        IcLogger::new().with_buffer(10).init().unwrap();
+        log_to("my_crate", Level::Warn, format_args!("first"));
+        log_to("my_crate", Level::Error, format_args!("second"));
+        let entries = get_logs();
+
+        assert_eq!(drain(), entries);
+        assert_eq!(entries.len(), 2);
+        assert!(get_logs().is_empty());
+        assert!(drain().is_empty());
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_timestamp_since_init() {
+        thread_local! {
+            static NOW: Cell<u64> = const { Cell::new(1_690_499_289_000_000_000) };
+        }
+        IcLogger::new()
+            .with_timestamps(true)
+            .with_timestamp_format(TimestampFormat::SinceInit)
+            .with_clock(|| NOW.get())
+            .init()
+            .unwrap();
+
+        let lines = capture(|| {
+            log_to("my_crate", Level::Warn, format_args!("started"));
+            NOW.set(NOW.get() + 250_000_000);
+            log_to("my_crate", Level::Warn, format_args!("loaded"));
+            NOW.set(NOW.get() + 61_999_999_999);
+            log_to("my_crate", Level::Warn, format_args!("done"));
+        });
+        assert_eq!(
+            lines,
+            [
+                "[+0.000s WARN  my_crate] started",
+                "[+0.250s WARN  my_crate] loaded",
+                "[+62.249s WARN  my_crate] done"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_newest_first() {
+        assert!(get_entries_newest_first().is_empty());
+
+        IcLogger::new().with_buffer(10).init().unwrap();
+        for message in ["first", "second", "third"] {
+            log_to("my_crate", Level::Warn, format_args!("{message}"));
+        }
+
+        let mut entries = get_logs();
+        entries.reverse();
+        assert_eq!(get_entries_newest_first(), entries);
+        assert_eq!(
+            export_chunks_newest_first(50),
+            [
+                "[WARN  my_crate] third\n[WARN  my_crate] second",
+                "[WARN  my_crate] first"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_context_fields() {
+        let logger = IcLogger::new();
+        let lines = capture_with(&logger, || {
+            crate::set_context_field("request_id", 42);
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("first")));
+            crate::set_context_field("caller", "aaaaa-aa");
+            crate::set_context_field("request_id", 43);
+            logger.log(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("my_crate")
+                    .args(format_args!("second"))
+                    .key_values(&[("id", 7)])
+                    .build(),
+            );
+            crate::clear_context_fields();
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("third")));
+        });
+
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] first request_id=42",
+                "[WARN  my_crate] second id=7 request_id=43 caller=aaaaa-aa",
+                "[WARN  my_crate] third"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timer_context() {
+        let logger = IcLogger::new();
+        let log = |message: &str| logger.log(&create_record("my_crate", Level::Warn, format_args!("{message}")));
+        let lines = capture_with(&logger, || {
+            let id = crate::with_timer_context(7, || {
+                log("fired");
+                crate::with_timer_context("nested", || log("nested"));
+                log("done");
+                7
+            });
+            log(&format!("after timer {id}"));
+        });
+
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] fired timer=7",
+                "[WARN  my_crate] nested timer=nested",
+                "[WARN  my_crate] done timer=7",
+                "[WARN  my_crate] after timer 7"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunking() {
+        let logger = IcLogger::new().with_chunking(8);
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("a long message")));
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("short")));
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("ééééé")));
+        });
+
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] a long m (part 1/2)",
+                "[WARN  my_crate] essage (part 2/2)",
+                "[WARN  my_crate] short",
+                "[WARN  my_crate] éééé (part 1/2)",
+                "[WARN  my_crate] é (part 2/2)"
+            ]
+        );
+
+        let logger = IcLogger::new().with_chunking(0);
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("éa")));
+        });
+        assert_eq!(
+            lines,
+            ["[WARN  my_crate] é (part 1/2)", "[WARN  my_crate] a (part 2/2)"]
+        );
+    }
+
+    #[test]
+    fn test_log_error() {
+        #[derive(Debug)]
+        struct Failure(&'static str, Option<Box<Failure>>);
+
+        impl fmt::Display for Failure {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+
+        impl std::error::Error for Failure {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                self.1.as_deref().map(|cause| cause as _)
+            }
+        }
+
+        let error = Failure(
+            "transfer failed",
+            Some(Box::new(Failure(
+                "ledger rejected the call",
+                Some(Box::new(Failure("insufficient funds", None))),
+            ))),
+        );
+
+        IcLogger::new().init().unwrap();
+        assert_eq!(
+            capture(|| log_error("payments", &error)),
+            [
+                "[ERROR payments] transfer failed",
+                "[ERROR payments] caused by: ledger rejected the call",
+                "[ERROR payments] caused by: insufficient funds"
+            ]
+        );
+
+        ensure_installed(IcLogger::new().with_format(Format::Json)).unwrap();
+        assert_eq!(
+            capture(|| log_error("payments", &error)),
+            [concat!(
+                r#"{"v":2,"level":"ERROR","target":"payments","msg":"transfer failed","#,
+                r#""causes":["ledger rejected the call","insufficient funds"]}"#
+            )]
+        );
+
+        ensure_installed(IcLogger::new().with_format(Format::Ndjson)).unwrap();
+        assert_eq!(
+            capture(|| log_error("payments", &error)),
+            [concat!(
+                r#"{"level":"ERROR","target":"payments","msg":"transfer failed","#,
+                r#""fields":{"causes":["ledger rejected the call","insufficient funds"]}}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn test_export_header() {
+        IcLogger::new()
+            .with_export_header()
+            .with_session_id()
+            .with_buffer(10)
+            .with_clock(|| 7)
+            .init()
+            .unwrap();
+        for message in ["first", "second", "third"] {
+            log_to("my_crate", Level::Warn, format_args!("{message}"));
+        }
+
+        let header = r#"{"v":2,"kind":"header","session":7}"#;
+        assert_eq!(
+            get_logs_text(),
+            format!("{header}\n[WARN  my_crate] first session=7\n[WARN  my_crate] second session=7\n[WARN  my_crate] third session=7")
+        );
+        let chunks = export_chunks(70);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].starts_with(&format!("{header}\n[WARN  my_crate] first")));
+        assert_eq!(chunks.iter().filter(|chunk| chunk.contains(header)).count(), 1);
+        assert!(get_logs().iter().all(|entry| !entry.line.contains("header")));
+    }
+
+    #[test]
+    fn test_init_from_str() {
+        assert!(matches!(
+            init_from_str("warn,my_crate=loud"),
+            Err(Error::InvalidLevel(token)) if token == "loud"
+        ));
+        assert!(!is_initialized());
+
+        init_from_str("warn,my_crate=debug,my_crate::db=off").unwrap();
+        assert!(would_log("my_crate", Level::Debug));
+        assert!(!would_log("my_crate::db", Level::Error));
+        assert!(would_log("other", Level::Warn));
+        assert!(!would_log("other", Level::Info));
+    }
+
+    #[test]
+    fn test_lossy_utf8() {
+        let bytes = b"caf\xc3\xa9 \xff\xfe frame";
+
+        IcLogger::new().init().unwrap();
+        assert_eq!(
+            capture(|| log_bytes("codec", Level::Warn, bytes)),
+            ["[WARN  codec] café \\xff\\xfe frame"]
+        );
+
+        ensure_installed(IcLogger::new().with_lossy_utf8()).unwrap();
+        assert_eq!(
+            capture(|| log_bytes("codec", Level::Warn, bytes)),
+            ["[WARN  codec] café \u{FFFD}\u{FFFD} frame"]
+        );
+        assert_eq!(
+            capture(|| log_bytes("codec", Level::Warn, b"valid")),
+            ["[WARN  codec] valid"]
+        );
+    }
+
+    #[test]
+    fn test_seen_targets() {
+        IcLogger::new().with_level(LevelFilter::Warn).init().unwrap();
+        assert!(seen_targets().is_empty());
+
+        log_to("my_crate::db", Level::Warn, format_args!("slow query"));
+        log_to("my_crate", Level::Debug, format_args!("filtered out"));
+        log_to("my_crate::db", Level::Error, format_args!("failed query"));
+        assert_eq!(seen_targets(), ["my_crate", "my_crate::db"]);
+
+        for index in 0..SEEN_TARGETS_LIMIT {
+            log_to(&format!("target_{index}"), Level::Warn, format_args!("message"));
+        }
+        let seen = seen_targets();
+        assert_eq!(seen.len(), SEEN_TARGETS_LIMIT);
+        assert!(seen.contains(&"my_crate".to_string()));
+    }
+
+    #[test]
+    fn test_buffer_accessors_before_init() {
+        /* A configuration that is built but not installed yet has no buffer to read from */
+        let _config = IcLogger::new().with_buffer(10).with_named_buffer("payments", 10);
+
+        assert!(get_logs().is_empty());
+        assert!(get_logs_text().is_empty());
+        assert!(get_entries_newest_first().is_empty());
+        assert!(get_named_logs("payments").is_empty());
+        assert!(tail(10, Level::Trace).is_empty());
+        assert!(export_chunks(100).is_empty());
+        assert!(export_chunks_newest_first(100).is_empty());
+        assert!(drain().is_empty());
+        assert_eq!(count_entries(|_entry| true), 0);
+        assert_eq!(last_entry_at_level(Level::Error), None);
+        assert_eq!(oversized_count(), 0);
+        assert!(!would_buffer(&create_log("my_crate", Level::Error)));
+        restore_logs(vec![LogEntry {
+            level: Level::Warn,
+            target: "my_crate".to_string(),
+            line: "[WARN  my_crate] restored".to_string(),
+        }]);
+        assert!(get_logs().is_empty());
+    }
+
+    #[test]
+    fn test_last_entry_at_level() {
+        IcLogger::new().with_buffer(10).init().unwrap();
+        for (level, message) in [
+            (Level::Error, "failed"),
+            (Level::Error, "failed again"),
+            (Level::Warn, "retrying"),
+        ] {
+            log_to("my_crate", level, format_args!("{message}"));
+        }
+
+        let last = |level| last_entry_at_level(level).map(|entry| entry.line);
+        assert_eq!(last(Level::Error).as_deref(), Some("[ERROR my_crate] failed again"));
+        assert_eq!(last(Level::Warn).as_deref(), Some("[WARN  my_crate] retrying"));
+        assert_eq!(last(Level::Info), None);
+    }
+
+    #[test]
+    fn test_count_entries() {
+        assert_eq!(count_entries(|_entry| true), 0);
+
+        IcLogger::new().with_buffer(10).init().unwrap();
+        for (level, message) in [
+            (Level::Error, "failed"),
+            (Level::Warn, "retrying"),
+            (Level::Error, "failed again"),
+            (Level::Info, "done"),
+        ] {
+            log_to("my_crate", level, format_args!("{message}"));
+        }
+
+        assert_eq!(count_entries(|entry| entry.level == Level::Error), 2);
+        assert_eq!(count_entries(|entry| entry.level <= Level::Warn), 3);
+        assert_eq!(count_entries(|entry| entry.target == "other"), 0);
+    }
+
+    #[test]
+    fn test_progressive_sampling() {
+        let logger = IcLogger::new().with_progressive_sampling("my_crate::sync", 2, 3);
+        let lines = capture_with(&logger, || {
+            for round in 0..9 {
+                let message = format_args!("round {round}");
+                logger.log(&create_record("my_crate::sync::batch", Level::Warn, message));
+            }
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("not sampled")));
+        });
+
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate::sync::batch] round 0",
+                "[WARN  my_crate::sync::batch] round 1",
+                "[WARN  my_crate::sync::batch] round 4",
+                "[WARN  my_crate::sync::batch] round 7",
+                "[WARN  my_crate] not sampled"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_correlation() {
+        thread_local! {
+            static NOW: Cell<u64> = const { Cell::new(7) };
+        }
+        IcLogger::new()
+            .with_auto_correlation(true)
+            .with_clock(|| NOW.get())
+            .init()
+            .unwrap();
+        let call = || {
+            let lines = capture(|| {
+                log_to("my_crate", Level::Warn, format_args!("first"));
+                log_to("my_crate", Level::Warn, format_args!("second"));
+            });
+            let ids: Vec<_> = lines
+                .iter()
+                .map(|line| line.rsplit_once("cid=").unwrap().1.to_string())
+                .collect();
+            assert_eq!(ids[0], ids[1]);
+            assert_eq!(ids[0].len(), 8);
+            ids[0].clone()
+        };
+
+        let first = call();
+        assert_eq!(call(), first);
+        NOW.set(8);
+        let second = call();
+        assert_ne!(second, first);
+        reset_correlation_id();
+        assert_ne!(call(), second);
+    }
+
+    #[test]
+    fn test_ring_buffer_bytes() {
+        IcLogger::new().with_ring_buffer_bytes(60).init().unwrap();
+        for message in ["first", "second", "third"] {
+            log_to("my_crate", Level::Warn, format_args!("{message}"));
+        }
+
+        /* Every line takes 22 or 23 bytes, only the last two fit */
+        let lines: Vec<_> = get_logs().into_iter().map(|entry| entry.line).collect();
+        assert_eq!(lines, ["[WARN  my_crate] second", "[WARN  my_crate] third"]);
+        assert!(describe().unwrap().contains("\nbuffer: 60 bytes"));
+
+        log_to("my_crate", Level::Warn, format_args!("{}", "x".repeat(60)));
+        assert_eq!(get_logs().len(), 2);
+        assert_eq!(oversized_count(), 1);
+    }
+
+    #[test]
+    fn test_named_buffer() {
+        IcLogger::new()
+            .with_buffer(10)
+            .with_named_buffer("payments", 10)
+            .init()
+            .unwrap();
+        log_to("payments::ledger", Level::Warn, format_args!("transfer failed"));
+        log_to("my_crate", Level::Warn, format_args!("low balance"));
+
+        let lines = |entries: Vec<LogEntry>| entries.into_iter().map(|entry| entry.line).collect::<Vec<_>>();
+        assert_eq!(
+            lines(get_named_logs("payments")),
+            ["[WARN  payments::ledger] transfer failed"]
+        );
+        assert_eq!(lines(get_logs()), ["[WARN  my_crate] low balance"]);
+        assert!(get_named_logs("unknown").is_empty());
+        assert!(would_buffer(&create_log("payments", Level::Warn)));
+    }
+
+    #[test]
+    fn test_session_id() {
+        thread_local! {
+            static NOW: Cell<u64> = const { Cell::new(7) };
+        }
+        let config = || {
+            IcLogger::new()
+                .with_session_id()
+                .with_buffer(10)
+                .with_clock(|| NOW.get())
+        };
+
+        config().init().unwrap();
+        log_to("my_crate", Level::Warn, format_args!("before upgrade"));
+        let saved = drain();
+
+        NOW.set(8);
+        ensure_installed(config()).unwrap();
+        restore_logs(saved);
+        log_to("my_crate", Level::Warn, format_args!("after upgrade"));
+
+        let lines: Vec<_> = get_logs().into_iter().map(|entry| entry.line).collect();
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] before upgrade session=7",
+                "[WARN  my_crate] after upgrade session=8"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail() {
+        assert!(tail(10, Level::Trace).is_empty());
+
+        IcLogger::new()
+            .with_level(LevelFilter::Debug)
+            .with_buffer(10)
+            .init()
+            .unwrap();
+        log_to("my_crate", Level::Error, format_args!("first"));
+        log_to("my_crate", Level::Debug, format_args!("second"));
+        log_to("my_crate", Level::Warn, format_args!("third"));
+        log_to("my_crate", Level::Info, format_args!("fourth"));
+        log_to("my_crate", Level::Error, format_args!("fifth"));
+
+        assert_eq!(
+            tail(2, Level::Warn),
+            ["[WARN  my_crate] third", "[ERROR my_crate] fifth"]
+        );
+        assert_eq!(
+            tail(5, Level::Error),
+            ["[ERROR my_crate] first", "[ERROR my_crate] fifth"]
+        );
+        assert_eq!(tail(1, Level::Trace), ["[ERROR my_crate] fifth"]);
+        assert!(tail(0, Level::Trace).is_empty());
+    }
+
+    #[test]
+    fn test_summary_level() {
+        let retry = || create_record("my_crate", Level::Warn, format_args!("retrying"));
+
+        let logger = IcLogger::new().with_dedup(true);
+        let lines = capture_with(&logger, || {
+            logger.log(&retry());
+            logger.log(&retry());
+            logger.log(&retry());
+            logger.log(&create_record("my_crate", Level::Error, format_args!("gave up")));
+        });
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] retrying",
+                "[WARN  my_crate] last message repeated 2 times",
+                "[ERROR my_crate] gave up"
+            ]
+        );
+
+        let logger = IcLogger::new().with_dedup(true).with_summary_level(Level::Info);
+        let lines = capture_with(&logger, || {
+            logger.log(&retry());
+            logger.log(&retry());
+            logger.flush();
+        });
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] retrying",
+                "[INFO  my_crate] last message repeated 1 time"
+            ]
+        );
+
+        let logger = |summary_level: Option<Level>| {
+            let logger = IcLogger::new()
+                .with_rate_limit(1, Duration::from_secs(1))
+                .with_clock(|| 1_000_000_000);
+            match summary_level {
+                Some(level) => logger.with_summary_level(level),
+                None => logger,
+            }
+        };
+        for (summary_level, summary) in [
+            (None, "[WARN  ic_logger] 2 records dropped by the rate limit"),
+            (
+                Some(Level::Info),
+                "[INFO  ic_logger] 2 records dropped by the rate limit",
+            ),
+        ] {
+            let logger = logger(summary_level);
+            let lines = capture_with(&logger, || {
+                logger.log(&retry());
+                logger.log(&retry());
+                logger.log(&retry());
+                logger.flush();
+                logger.flush();
+            });
+            assert_eq!(lines, ["[WARN  my_crate] retrying", summary]);
+        }
+    }
+
+    #[test]
+    fn test_batching() {
+        IcLogger::new().with_batching(true).init().unwrap();
+        let pending = || with_logger(|logger| logger.pending.as_ref().unwrap().lock().unwrap().clone()).unwrap();
+
+        log::logger().log(&create_record("my_crate", Level::Warn, format_args!("first")));
+        log::logger().log(&create_record("my_crate", Level::Error, format_args!("second")));
+        assert_eq!(pending(), ["[WARN  my_crate] first", "[ERROR my_crate] second"]);
+
+        flush_on_heartbeat();
+        assert!(pending().is_empty());
+    }
+
+    #[test]
+    fn test_batch_flush_level() {
+        IcLogger::new()
+            .with_level(LevelFilter::Debug)
+            .with_batching(true)
+            .with_batch_flush_level(Level::Error)
+            .init()
+            .unwrap();
+        let pending = || with_logger(|logger| logger.pending.as_ref().unwrap().lock().unwrap().clone()).unwrap();
+
+        log::logger().log(&create_record("my_crate", Level::Debug, format_args!("first")));
+        log::logger().log(&create_record("my_crate", Level::Warn, format_args!("second")));
+        assert_eq!(pending(), ["[DEBUG my_crate] first", "[WARN  my_crate] second"]);
+
+        log::logger().log(&create_record("my_crate", Level::Error, format_args!("third")));
+        assert!(pending().is_empty());
+        log::logger().log(&create_record("my_crate", Level::Debug, format_args!("fourth")));
+        assert_eq!(pending(), ["[DEBUG my_crate] fourth"]);
+    }
+
+    #[test]
+    fn test_json_schema_version() {
+        let record = create_record("my_crate", Level::Warn, format_args!("message"));
+
+        assert_eq!(
+            IcLogger::new().with_format(Format::Json).format_record(&record),
+            r#"{"v":2,"level":"WARN","target":"my_crate","msg":"message"}"#
+        );
+        assert_eq!(
+            IcLogger::new()
+                .with_format(Format::Json)
+                .with_json_schema_version(3)
+                .format_record(&record),
+            r#"{"v":3,"level":"WARN","target":"my_crate","msg":"message"}"#
+        );
+    }
+
+    #[test]
+    fn test_sink_formats() {
+        use std::sync::Arc;
+
+        let console = Arc::new(Mutex::new(Vec::new()));
+        let json = Arc::new(Mutex::new(Vec::new()));
+        let sink = |lines: &Arc<Mutex<Vec<String>>>| {
+            let lines = Arc::clone(lines);
+            move |_: Level, _: &str, line: &str| lines.lock().unwrap().push(line.to_string())
+        };
+        let logger = IcLogger::new()
+            .add_sink(sink(&console))
+            .add_sink_with_format(sink(&json), Format::Json);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("disk full")));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] disk full"]);
+        assert_eq!(*console.lock().unwrap(), lines);
+        assert_eq!(
+            *json.lock().unwrap(),
+            [r#"{"v":2,"level":"WARN","target":"my_crate","msg":"disk full"}"#]
+        );
+    }
+
+    #[test]
+    fn test_target_sinks() {
+        use std::sync::Arc;
+
+        let sink = |lines: &Arc<Mutex<Vec<String>>>| {
+            let lines = Arc::clone(lines);
+            move |_: Level, _: &str, line: &str| lines.lock().unwrap().push(line.to_string())
+        };
+        let (metrics, audit, all) = Default::default();
+        let logger = IcLogger::new()
+            .with_target_sink("my_crate::metrics", sink(&metrics))
+            .with_target_sink_and_default("my_crate::audit", sink(&audit))
+            .add_sink(sink(&all));
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record(
+                "my_crate::metrics",
+                Level::Warn,
+                format_args!("calls=3"),
+            ));
+            logger.log(&create_record("my_crate::audit", Level::Warn, format_args!("upgraded")));
+            logger.log(&create_record("my_crate::api", Level::Warn, format_args!("failed")));
+        });
+        assert_eq!(*metrics.lock().unwrap(), ["[WARN  my_crate::metrics] calls=3"]);
+        assert_eq!(*audit.lock().unwrap(), ["[WARN  my_crate::audit] upgraded"]);
+        assert_eq!(
+            lines,
+            ["[WARN  my_crate::audit] upgraded", "[WARN  my_crate::api] failed"]
+        );
+        assert_eq!(*all.lock().unwrap(), lines);
+    }
+
+    #[test]
+    fn test_split_streams() {
+        use std::sync::Arc;
+
+        /// A sink with a separate error output.
+        struct Streams(Arc<Mutex<Vec<(Stream, String)>>>);
+
+        impl Sink for Streams {
+            fn write(&self, level: Level, target: &str, line: &str) {
+                self.write_stream(Stream::Stdout, level, target, line)
+            }
+
+            fn write_stream(&self, stream: Stream, _: Level, _: &str, line: &str) {
+                self.0.lock().unwrap().push((stream, line.to_string()));
+            }
+        }
+
+        let streams = Arc::new(Mutex::new(Vec::new()));
+        let plain = Arc::new(Mutex::new(Vec::new()));
+        let logger = IcLogger::new()
+            .with_level(LevelFilter::Info)
+            .with_split_streams()
+            .add_sink(Streams(Arc::clone(&streams)))
+            .add_sink({
+                let plain = Arc::clone(&plain);
+                move |_: Level, _: &str, line: &str| plain.lock().unwrap().push(line.to_string())
+            });
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Error, format_args!("failed")));
+            logger.log(&create_record("my_crate", Level::Info, format_args!("started")));
+        });
+        assert_eq!(lines, ["[stderr] [ERROR my_crate] failed", "[INFO  my_crate] started"]);
+        assert_eq!(*plain.lock().unwrap(), lines);
+        assert_eq!(
+            *streams.lock().unwrap(),
+            [
+                (Stream::Stderr, "[ERROR my_crate] failed".to_string()),
+                (Stream::Stdout, "[INFO  my_crate] started".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debug_sink() {
+        use std::sync::Arc;
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let lines = Arc::clone(&lines);
+            move |_: Level, _: &str, line: &str| lines.lock().unwrap().push(line.to_string())
+        };
+        let logger = IcLogger::new().with_debug_sink(sink);
+
+        capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("diagnostics")));
+        });
+        #[cfg(debug_assertions)]
+        assert_eq!(*lines.lock().unwrap(), ["[WARN  my_crate] diagnostics"]);
+        #[cfg(not(debug_assertions))]
+        assert!(lines.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_line_interceptor() {
+        let logger = IcLogger::new()
+            .with_buffer(10)
+            .with_line_interceptor(|line| match line.contains("heartbeat") {
+                true => None,
+                false => Some(line.replace("hunter2", "*******")),
+            });
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record(
+                "my_crate",
+                Level::Warn,
+                format_args!("heartbeat missed"),
+            ));
+            logger.log(&create_record(
+                "my_crate",
+                Level::Warn,
+                format_args!("password hunter2"),
+            ));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] password *******"]);
+        assert_eq!(logger.buffer().unwrap().entries().count(), 1);
+    }
+
+    #[test]
+    fn test_clear_error_callbacks() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let errors = Arc::new(AtomicUsize::new(0));
+        let callback = || {
+            let errors = Arc::clone(&errors);
+            move |_: &str| {
+                errors.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+        let mut logger = IcLogger::new()
+            .with_forwarder(Principal::anonymous(), "ingest")
+            .on_error(callback())
+            .on_error(callback());
+        logger.forwarder.as_mut().unwrap().send = |_, _, _| Err(RejectionCode::DestinationInvalid);
+        let record = || create_record("my_crate", Level::Error, format_args!("failed"));
+
+        assert_eq!(logger.error_callback_count(), 2);
+        capture_with(&logger, || logger.log(&record()));
+        assert_eq!(errors.load(Ordering::Relaxed), 2);
+
+        logger.clear_error_callbacks();
+        assert_eq!(logger.error_callback_count(), 0);
+        capture_with(&logger, || logger.log(&record()));
+        assert_eq!(errors.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_stable_size() {
+        let mut logger = IcLogger::new().with_stable_size();
+        logger.stable_size = Some(|| 131_072);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("migrated users")));
+            logger.log(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("my_crate")
+                    .args(format_args!("migrated users"))
+                    .key_values(&[("count", 3)])
+                    .build(),
+            );
+        });
+        assert_eq!(
+            lines,
+            [
+                "[WARN  my_crate] migrated users stable_size=131072",
+                "[WARN  my_crate] migrated users count=3 stable_size=131072"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instructions_remaining() {
+        let mut logger = IcLogger::new()
+            .with_instructions_remaining()
+            .with_instruction_counter(|| 750);
+        logger.instructions_remaining = Some(1000);
+
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("batch done")));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] batch done rem=250"]);
+
+        logger.instruction_counter = || 1500;
+        let lines = capture_with(&logger, || {
+            logger.log(&create_record("my_crate", Level::Warn, format_args!("batch done")));
+        });
+        assert_eq!(lines, ["[WARN  my_crate] batch done rem=0"]);
+    }
+
+    #[cfg(feature = "production")]
+    #[test]
+    fn test_production_trace_warning() {
+        let warned = |logger: IcLogger| {
+            *logger.captured() = Some(Vec::new());
+            logger.init().unwrap();
+            let lines = with_logger(|logger| logger.captured().take().unwrap()).unwrap();
+            LOGGER.with(|installed| installed.take());
+            INSTALLED.set(false);
+            lines.into_iter().map(|(_level, line)| line).collect::<Vec<_>>()
+        };
+
+        assert!(warned(IcLogger::new().with_level(LevelFilter::Debug)).is_empty());
+        assert_eq!(
+            warned(IcLogger::new().with_module_level("my_crate", LevelFilter::Trace)),
+            ["[WARN  ic_logger] trace logging is enabled, which is costly on-chain"]
+        );
+        assert!(warned(IcLogger::new().with_level(LevelFilter::Trace)).is_empty());
+    }
+
+    /// Capture the lines `logger` writes while running `f`, without installing it.
+    fn capture_with(logger: &IcLogger, f: impl FnOnce()) -> Vec<String> {
+        *logger.captured() = Some(Vec::new());
+        f();
+        let captured = logger.captured().take().unwrap();
+        captured.into_iter().map(|(_level, line)| line).collect()
+    }
+
+    fn create_log(name: &str, level: Level) -> Metadata<'_> {
+        let mut builder = Metadata::builder();
+        builder.level(level);
+        builder.target(name);
+        builder.build()
+    }
+
+    fn create_record<'a>(name: &'a str, level: Level, args: std::fmt::Arguments<'a>) -> Record<'a> {
+        Record::builder().level(level).target(name).args(args).build()
+    }
+}
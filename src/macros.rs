@@ -0,0 +1,114 @@
+//! Declarative configuration of the logger.
+
+/// Build an [`IcLogger`](crate::IcLogger) from a table of levels.
+///
+/// The `default` level comes first, followed by the levels of specific modules, all written as
+/// lowercase [`LevelFilter`](log::LevelFilter) names:
+///
+/// ```no_run
+/// #[ic_cdk::init]
+/// fn init() {
+///     ic_logger::ic_log_filters! {
+///         default = warn,
+///         "my_canister::db" = trace,
+///         "my_canister::api" = info,
+///     }
+///     .init()
+///     .unwrap();
+/// }
+/// ```
+///
+/// This expands to the equivalent chain of [`with_level`](crate::IcLogger::with_level) and
+/// [`with_module_level`](crate::IcLogger::with_module_level) calls, so the result can be
+/// configured further before it is initialised.
+#[macro_export]
+macro_rules! ic_log_filters {
+    (@level off) => { $crate::__private::LevelFilter::Off };
+    (@level error) => { $crate::__private::LevelFilter::Error };
+    (@level warn) => { $crate::__private::LevelFilter::Warn };
+    (@level info) => { $crate::__private::LevelFilter::Info };
+    (@level debug) => { $crate::__private::LevelFilter::Debug };
+    (@level trace) => { $crate::__private::LevelFilter::Trace };
+    (default = $default:ident $(, $module:literal = $level:ident)* $(,)?) => {
+        $crate::IcLogger::new()
+            .with_level($crate::ic_log_filters!(@level $default))
+            $(.with_module_level($module, $crate::ic_log_filters!(@level $level)))*
+    };
+}
+
+/// Log a message at the given level only the first time `key` is used, see [`log_once`].
+///
+/// This is the macro behind [`error_once!`](crate::error_once!), [`warn_once!`](crate::warn_once!),
+/// [`info_once!`](crate::info_once!), [`debug_once!`](crate::debug_once!) and
+/// [`trace_once!`](crate::trace_once!), which log under the calling module.
+///
+/// [`log_once`]: fn@crate::log_once
+#[macro_export]
+macro_rules! log_once {
+    ($key:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log_once($key, module_path!(), $level, format_args!($($arg)+))
+    };
+}
+
+/// Log an error only the first time `key` is used, see [`log_once!`](crate::log_once!).
+#[macro_export]
+macro_rules! error_once {
+    ($key:expr, $($arg:tt)+) => { $crate::log_once!($key, $crate::__private::Level::Error, $($arg)+) };
+}
+
+/// Log a warning only the first time `key` is used, see [`log_once!`](crate::log_once!).
+#[macro_export]
+macro_rules! warn_once {
+    ($key:expr, $($arg:tt)+) => { $crate::log_once!($key, $crate::__private::Level::Warn, $($arg)+) };
+}
+
+/// Log an info message only the first time `key` is used, see [`log_once!`](crate::log_once!).
+#[macro_export]
+macro_rules! info_once {
+    ($key:expr, $($arg:tt)+) => { $crate::log_once!($key, $crate::__private::Level::Info, $($arg)+) };
+}
+
+/// Log a debug message only the first time `key` is used, see [`log_once!`](crate::log_once!).
+#[macro_export]
+macro_rules! debug_once {
+    ($key:expr, $($arg:tt)+) => { $crate::log_once!($key, $crate::__private::Level::Debug, $($arg)+) };
+}
+
+/// Log a trace message only the first time `key` is used, see [`log_once!`](crate::log_once!).
+#[macro_export]
+macro_rules! trace_once {
+    ($key:expr, $($arg:tt)+) => { $crate::log_once!($key, $crate::__private::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::IcLogger;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_ic_log_filters() {
+        let logger = ic_log_filters! {
+            default = warn,
+            "my_crate::db" = trace,
+            "my_crate" = info,
+        };
+        let manual = IcLogger::new()
+            .with_level(LevelFilter::Warn)
+            .with_module_level("my_crate::db", LevelFilter::Trace)
+            .with_module_level("my_crate", LevelFilter::Info);
+
+        assert_eq!(logger.resolved_rules(), manual.resolved_rules());
+        assert_eq!(
+            logger.resolved_rules(),
+            [
+                ("my_crate::db".to_string(), LevelFilter::Trace),
+                ("my_crate".to_string(), LevelFilter::Info),
+                (String::new(), LevelFilter::Warn)
+            ]
+        );
+        assert_eq!(
+            ic_log_filters! { default = off }.resolved_rules(),
+            [(String::new(), LevelFilter::Off)]
+        );
+    }
+}
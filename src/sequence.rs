@@ -0,0 +1,75 @@
+//! A logical clock numbering the written records.
+
+use std::sync::{Mutex, PoisonError};
+
+/// When the logical clock of [`IcLogger::with_logical_clock`](crate::IcLogger::with_logical_clock)
+/// starts counting from zero again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SequenceReset {
+    /// Never, the number grows with every record for the lifetime of the logger
+    #[default]
+    Never,
+    /// Whenever the time of the clock changes, so the number orders records sharing a timestamp
+    OnTimeChange,
+}
+
+/// Numbers records in the order they are written.
+pub(crate) struct Sequence {
+    reset: SequenceReset,
+
+    /// The time of the previous record, if any, and the number of the next one
+    state: Mutex<(Option<u64>, u64)>,
+}
+
+impl Sequence {
+    pub(crate) fn new(reset: SequenceReset) -> Sequence {
+        Sequence {
+            reset,
+            state: Mutex::new((None, 0)),
+        }
+    }
+
+    /// When the sequence starts counting from zero again.
+    pub(crate) fn reset(&self) -> SequenceReset {
+        self.reset
+    }
+
+    /// The number of a record written at `now`, in nanoseconds since the unix epoch.
+    pub(crate) fn next(&self, now: u64) -> u64 {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (time, next) = &mut *state;
+        if self.reset == SequenceReset::OnTimeChange && *time != Some(now) {
+            *next = 0;
+        }
+        *time = Some(now);
+        *next += 1;
+        *next - 1
+    }
+
+    /// The number [`next`](Sequence::next) would return, without taking it.
+    pub(crate) fn peek(&self, now: u64) -> u64 {
+        let (time, next) = *self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.reset == SequenceReset::OnTimeChange && time != Some(now) {
+            0
+        } else {
+            next
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next() {
+        let sequence = Sequence::new(SequenceReset::Never);
+        assert_eq!([5, 5, 6, 6].map(|now| sequence.next(now)), [0, 1, 2, 3]);
+
+        let sequence = Sequence::new(SequenceReset::OnTimeChange);
+        assert_eq!([5, 5, 6, 6, 6].map(|now| sequence.next(now)), [0, 1, 0, 1, 2]);
+        assert_eq!(sequence.peek(6), 3);
+        assert_eq!(sequence.peek(7), 0);
+        assert_eq!(sequence.next(6), 3);
+    }
+}
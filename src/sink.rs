@@ -0,0 +1,66 @@
+//! Additional destinations for log lines.
+
+use log::Level;
+
+/// The prefix of lines meant for [`Stream::Stderr`] that are written to a destination without one.
+pub const STDERR_MARKER: &str = "[stderr] ";
+
+/// An output stream of a destination, see [`IcLogger::with_split_streams`](crate::IcLogger::with_split_streams).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// The regular output
+    Stdout,
+    /// The error output
+    Stderr,
+}
+
+impl Stream {
+    /// The stream records at `level` are written to: [`Stream::Stderr`] for errors and warnings,
+    /// [`Stream::Stdout`] for everything else.
+    pub fn for_level(level: Level) -> Stream {
+        match level {
+            Level::Error | Level::Warn => Stream::Stderr,
+            Level::Info | Level::Debug | Level::Trace => Stream::Stdout,
+        }
+    }
+}
+
+/// A destination that receives every logged line, next to the [`Backend`](crate::Backend).
+///
+/// Sinks are registered with [`IcLogger::add_sink`](crate::IcLogger::add_sink) or, to receive
+/// lines in a format of their own, [`IcLogger::add_sink_with_format`](crate::IcLogger::add_sink_with_format).
+///
+/// Closures taking the level, target and line implement this trait:
+///
+/// ```no_run
+/// use ic_logger::{Format, IcLogger};
+///
+/// IcLogger::new()
+///     .add_sink_with_format(|_level, _target: &str, line: &str| ic_cdk::println!("{line}"), Format::Json)
+///     .init()
+///     .unwrap();
+/// ```
+pub trait Sink: Send + Sync {
+    /// Write a line rendered from a record with the given level and target.
+    fn write(&self, level: Level, target: &str, line: &str);
+
+    /// Write a line to one of the streams of the sink.
+    ///
+    /// Sinks with a separate error output override this, by default lines for [`Stream::Stderr`]
+    /// are passed to [`write`](Sink::write) with the [`STDERR_MARKER`] prefix.
+    fn write_stream(&self, stream: Stream, level: Level, target: &str, line: &str) {
+        match stream {
+            Stream::Stdout => self.write(level, target, line),
+            Stream::Stderr => self.write(level, target, &format!("{STDERR_MARKER}{line}")),
+        }
+    }
+}
+
+impl<F> Sink for F
+where
+    F: Fn(Level, &str, &str) + Send + Sync,
+{
+    fn write(&self, level: Level, target: &str, line: &str) {
+        self(level, target, line)
+    }
+}
@@ -0,0 +1,101 @@
+//! Lightweight scope timing without a tracing dependency.
+
+use log::{Level, Record};
+
+use crate::logger::instructions;
+
+/// The target of the records written by [`span`].
+pub const SPAN_TARGET: &str = "ic_logger::span";
+
+/// Log `-> name` now and `<- name (Δ N instructions)` when the returned guard is dropped.
+///
+/// `N` is the number of instructions the message executed in between, which gives a rough cost
+/// of the scope. Both records are logged at [`Level::Trace`] under [`SPAN_TARGET`], so they only
+/// show up when that target is enabled:
+///
+/// ```no_run
+/// use ic_logger::{IcLogger, SPAN_TARGET};
+/// use log::LevelFilter;
+///
+/// IcLogger::new().with_module_level(SPAN_TARGET, LevelFilter::Trace).init().unwrap();
+///
+/// fn migrate() {
+///     let _span = ic_logger::span("migrate");
+///     // ...
+/// }
+/// ```
+pub fn span(name: &str) -> SpanGuard {
+    let guard = SpanGuard {
+        name: name.to_string(),
+        start: instructions(),
+    };
+    write(format_args!("-> {}", guard.name));
+    guard
+}
+
+/// Logs the end of a [`span`] when dropped.
+#[must_use = "The span ends when the guard is dropped"]
+pub struct SpanGuard {
+    name: String,
+
+    /// The instruction counter when the span started
+    start: u64,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let instructions = instructions().saturating_sub(self.start);
+        write(format_args!("<- {} (Δ {instructions} instructions)", self.name));
+    }
+}
+
+/// Log a record of a span.
+fn write(args: std::fmt::Arguments) {
+    log::logger().log(
+        &Record::builder()
+            .target(SPAN_TARGET)
+            .level(Level::Trace)
+            .args(args)
+            .build(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::IcLogger;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_span() {
+        thread_local! {
+            static INSTRUCTIONS: Cell<u64> = const { Cell::new(0) };
+        }
+        IcLogger::new()
+            .with_module_level(SPAN_TARGET, LevelFilter::Trace)
+            .with_instruction_counter(|| {
+                INSTRUCTIONS.set(INSTRUCTIONS.get() + 100);
+                INSTRUCTIONS.get()
+            })
+            .init()
+            .unwrap();
+
+        let lines = crate::capture(|| {
+            let _outer = span("migrate");
+            {
+                let _inner = span("users");
+            }
+        });
+        assert_eq!(
+            lines,
+            [
+                "[TRACE ic_logger::span] -> migrate",
+                "[TRACE ic_logger::span] -> users",
+                "[TRACE ic_logger::span] <- users (Δ 100 instructions)",
+                "[TRACE ic_logger::span] <- migrate (Δ 300 instructions)",
+            ]
+        );
+    }
+}
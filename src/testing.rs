@@ -0,0 +1,58 @@
+//! Helpers for testing code that logs.
+
+use log::Level;
+
+use crate::logger::capture_levels;
+
+/// Run `f` and panic if it logged any record at `level` or a more severe one.
+///
+/// This catches unexpected errors in tests, e.g. with `Level::Error` only warnings and less
+/// severe records are allowed. The lines are captured like with [`capture`](crate::capture), so
+/// the logger has to be initialised with a level that lets the records through:
+///
+/// ```no_run
+/// use log::Level;
+///
+/// ic_logger::init().unwrap();
+/// ic_logger::testing::assert_no_logs_above(Level::Error, || log::warn!("retrying"));
+/// ```
+#[track_caller]
+pub fn assert_no_logs_above(level: Level, f: impl FnOnce()) {
+    let offending: Vec<_> = capture_levels(f)
+        .into_iter()
+        .filter(|(line_level, _line)| *line_level <= level)
+        .map(|(_level, line)| line)
+        .collect();
+    assert!(
+        offending.is_empty(),
+        "expected no logs at {level} or above, got:\n{}",
+        offending.join("\n")
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{log_to, IcLogger};
+
+    #[test]
+    fn test_assert_no_logs_above() {
+        IcLogger::new().init().unwrap();
+
+        assert_no_logs_above(Level::Error, || {
+            log_to("my_crate", Level::Warn, format_args!("retrying"));
+        });
+
+        let panic = std::panic::catch_unwind(|| {
+            assert_no_logs_above(Level::Warn, || {
+                log_to("my_crate", Level::Warn, format_args!("retrying"));
+                log_to("my_crate", Level::Error, format_args!("failed"));
+            });
+        });
+        let message = *panic.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "expected no logs at WARN or above, got:\n[WARN  my_crate] retrying\n[ERROR my_crate] failed"
+        );
+    }
+}
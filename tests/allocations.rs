@@ -0,0 +1,88 @@
+//! Allocations made while writing lines, counted by the global allocator of this test only.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use ic_logger::IcLogger;
+use log::{Level, LevelFilter, Log, Record};
+
+/// Counts the allocations of each thread, so that tests running in parallel don't interfere.
+struct CountingAllocator;
+
+thread_local! {
+    /// The number of allocations and of allocated bytes
+    static ALLOCATIONS: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| {
+            let (count, bytes) = allocations.get();
+            allocations.set((count + 1, bytes + layout.size()));
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations and of allocated bytes made on this thread while running `f`.
+fn count_allocations(f: impl FnOnce()) -> (usize, usize) {
+    let (count, bytes) = ALLOCATIONS.get();
+    f();
+    let (count_after, bytes_after) = ALLOCATIONS.get();
+    (count_after - count, bytes_after - bytes)
+}
+
+/// A logger writing to `sinks` only, printing would allocate in the output captured by the tests.
+fn sinks_only() -> IcLogger {
+    IcLogger::new()
+        .with_console_and_buffer(LevelFilter::Off, LevelFilter::Off)
+        .with_level(LevelFilter::Warn)
+}
+
+#[test]
+fn test_write_allocations() {
+    let logger = sinks_only()
+        .add_sink(|_level, _target: &str, _line: &str| {})
+        .add_sink(|_level, _target: &str, _line: &str| {});
+    let record = Record::builder()
+        .level(Level::Warn)
+        .target("my_crate::db")
+        .args(format_args!("slow query"))
+        .build();
+    /* Only a target that wasn't seen before is copied, once */
+    logger.log(&record);
+
+    /* Writing a line to the sinks takes no allocations on top of rendering it, neither the target
+     * nor the line are copied.
+     */
+    let rendering = count_allocations(|| drop(logger.render(&record)));
+    let logging = count_allocations(|| logger.log(&record));
+    assert_eq!(logging, rendering);
+}
+
+#[test]
+fn test_message_allocations() {
+    let logger = sinks_only();
+    let message = "x".repeat(10_000);
+
+    /* The message is rendered into a string and copied into the line once, not copied in between */
+    let (_count, bytes) = count_allocations(|| {
+        drop(
+            logger.render(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("my_crate")
+                    .args(format_args!("{message}"))
+                    .build(),
+            ),
+        )
+    });
+    assert!(bytes < 3 * message.len(), "{bytes} bytes allocated");
+}